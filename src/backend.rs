@@ -0,0 +1,104 @@
+use serde_json::Value;
+use std::io::{self, Read};
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use crate::config::CollectionConfig;
+use crate::error::DBError;
+use crate::filesystem::{FileSystem, RetryPolicy};
+
+/// Trait for the storage of a single collection, see [crate::db::TDatabase::mount_collection].
+/// The database keeps handling uuid uniqueness, canonicalization, configuration and indexes on
+/// top of the records read from and written to the backend
+pub trait CollectionBackend: Send + Sync {
+    /// Reads every record of the collection
+    fn read_all(&self) -> Result<Vec<Value>, DBError<'static>>;
+    /// Replaces every record of the collection
+    fn write_all(&self, records: Vec<Value>) -> Result<(), DBError<'static>>;
+}
+
+/// Backend storing a collection as a json array in a single file, collections that are not
+/// mounted are stored this way in the database folder
+#[derive(Clone)]
+pub struct FileBackend {
+    fs: Arc<dyn FileSystem>,
+    path: PathBuf,
+    retry: RetryPolicy,
+    config: CollectionConfig,
+}
+
+impl FileBackend {
+    /// Creates a backend storing the collection in the file at `path` of `fs`, the file is
+    /// created by the first write
+    pub fn new(fs: Arc<dyn FileSystem>, path: PathBuf) -> FileBackend {
+        FileBackend {
+            fs,
+            path,
+            retry: RetryPolicy::default(),
+            config: CollectionConfig::default(),
+        }
+    }
+
+    /// Retries reads and writes failing with a transient io error, see [RetryPolicy]
+    pub fn with_retry(mut self, retry: RetryPolicy) -> FileBackend {
+        self.retry = retry;
+        self
+    }
+
+    /// Writes the records the way `config` stores them, compact json by default
+    pub fn with_config(mut self, config: CollectionConfig) -> FileBackend {
+        self.config = config;
+        self
+    }
+
+    /// Opens the collection file for streaming reads
+    pub fn open(&self) -> Result<Box<dyn Read + Send>, DBError<'static>> {
+        self.retry
+            .run(|| self.fs.open(&self.path))
+            .map_err(|_| DBError::new("Could not read collection"))
+    }
+}
+
+impl CollectionBackend for FileBackend {
+    fn read_all(&self) -> Result<Vec<Value>, DBError<'static>> {
+        let r = match self.retry.run(|| self.fs.read_to_string(&self.path)) {
+            Ok(r) => r,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                return Result::Err(DBError::new("Collection was deleted"))
+            }
+            Err(_) => return Result::Err(DBError::new("Could not read collection")),
+        };
+        serde_json::from_str(&r).map_err(|_| DBError::new("Could not parse collection"))
+    }
+
+    fn write_all(&self, records: Vec<Value>) -> Result<(), DBError<'static>> {
+        let content = self.config.to_json(&records);
+        self.retry
+            .run(|| self.fs.write(&self.path, content.as_bytes()))
+            .map_err(|_| DBError::new("Could not write collection"))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::filesystem::StdFileSystem;
+    use serde_json::json;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_file_backend() {
+        let dir = tempdir().unwrap();
+        let backend = FileBackend::new(Arc::new(StdFileSystem), dir.path().join("test.json"));
+        assert!(backend.read_all().is_err());
+        backend.write_all(vec![json!({"uuid": "a"})]).unwrap();
+        assert_eq!(backend.read_all().unwrap(), vec![json!({"uuid": "a"})]);
+        let mut content = String::new();
+        backend
+            .open()
+            .unwrap()
+            .read_to_string(&mut content)
+            .unwrap();
+        assert_eq!(content, r#"[{"uuid":"a"}]"#);
+    }
+}
@@ -0,0 +1,97 @@
+use serde_json::Value;
+
+/// Cached records of a collection
+pub(crate) struct CacheEntry {
+    pub(crate) name: String,
+    pub(crate) records: Vec<Value>,
+    /// True if the records were changed since they were last written to disk
+    pub(crate) dirty: bool,
+}
+
+/// Least recently used cache of collection records, holding at most `capacity` collections
+pub(crate) struct CollectionCache {
+    capacity: usize,
+    /// Entries ordered from least to most recently used
+    entries: Vec<CacheEntry>,
+}
+
+impl CollectionCache {
+    pub(crate) fn new(capacity: usize) -> CollectionCache {
+        CollectionCache {
+            capacity,
+            entries: Vec::new(),
+        }
+    }
+
+    /// Returns the records of a cached collection, marking it as most recently used
+    pub(crate) fn get(&mut self, name: &str) -> Option<&CacheEntry> {
+        let i = self.entries.iter().position(|e| e.name == name)?;
+        let entry = self.entries.remove(i);
+        self.entries.push(entry);
+        self.entries.last()
+    }
+
+    /// Caches the records of a collection as most recently used, returns the entries evicted
+    /// to stay within capacity
+    pub(crate) fn put(&mut self, name: &str, records: Vec<Value>, dirty: bool) -> Vec<CacheEntry> {
+        let dirty = match self.remove(name) {
+            Some(entry) => dirty || entry.dirty,
+            None => dirty,
+        };
+        self.entries.push(CacheEntry {
+            name: name.to_string(),
+            records,
+            dirty,
+        });
+        let overflow = self.entries.len().saturating_sub(self.capacity);
+        self.entries.drain(..overflow).collect()
+    }
+
+    /// Puts back an entry that could not be written as least recently used, so it is the next
+    /// to be evicted. The cache may exceed its capacity until then, an entry cached since is kept
+    pub(crate) fn restore(&mut self, entry: CacheEntry) {
+        if self.entries.iter().all(|e| e.name != entry.name) {
+            self.entries.insert(0, entry);
+        }
+    }
+
+    /// Removes a collection from the cache
+    pub(crate) fn remove(&mut self, name: &str) -> Option<CacheEntry> {
+        let i = self.entries.iter().position(|e| e.name == name)?;
+        Some(self.entries.remove(i))
+    }
+
+    /// Removes every collection from the cache
+    pub(crate) fn drain(&mut self) -> Vec<CacheEntry> {
+        self.entries.drain(..).collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_lru_eviction() {
+        let mut cache = CollectionCache::new(2);
+        assert!(cache.put("a", vec![], true).is_empty());
+        assert!(cache.put("b", vec![], false).is_empty());
+        assert!(cache.get("a").is_some());
+        let evicted = cache.put("c", vec![], false);
+        assert_eq!(evicted.len(), 1);
+        assert_eq!(evicted[0].name, "b");
+        assert!(cache.get("b").is_none());
+        // re-caching a dirty entry keeps it dirty
+        cache.put("a", vec![], false);
+        assert!(cache.get("a").unwrap().dirty);
+        // restored entries are evicted first
+        cache.restore(CacheEntry {
+            name: "d".to_string(),
+            records: vec![],
+            dirty: true,
+        });
+        let evicted = cache.put("e", vec![], false);
+        assert_eq!(evicted.len(), 2);
+        assert_eq!(evicted[0].name, "d");
+    }
+}
@@ -0,0 +1,140 @@
+use std::io::Read;
+use std::marker::PhantomData;
+
+use crate::db::{Data, Database};
+use crate::error::DBError;
+use crate::stream::RecordStream;
+
+/// Typed handle to a collection, created by [Database::collection]
+pub struct Collection<'a, T: Data> {
+    db: &'a Database,
+    name: String,
+    _data: PhantomData<T>,
+}
+
+impl<'a, T: Data> Collection<'a, T> {
+    pub(crate) fn new(db: &'a Database, name: &str) -> Collection<'a, T> {
+        Collection {
+            db,
+            name: name.to_string(),
+            _data: PhantomData,
+        }
+    }
+
+    /// Name of the collection
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Streams the records of the collection, records that can not be parsed are returned as
+    /// errors without ending the iteration
+    pub fn iter(&self) -> Result<CollectionIter<'a, T>, DBError<'a>> {
+        let reader = self.db.open_collection_reader(&self.name)?;
+        Result::Ok(CollectionIter {
+            records: Some(RecordStream::new(reader)),
+            error: None,
+            _data: PhantomData,
+        })
+    }
+}
+
+impl<'a, T: Data> IntoIterator for &Collection<'a, T> {
+    type Item = Result<T, DBError<'a>>;
+    type IntoIter = CollectionIter<'a, T>;
+
+    /// Same as [Collection::iter], an error opening the collection is returned as the only item
+    fn into_iter(self) -> CollectionIter<'a, T> {
+        self.iter().unwrap_or_else(|e| CollectionIter {
+            records: None,
+            error: Some(e),
+            _data: PhantomData,
+        })
+    }
+}
+
+/// Iterator over the records of a [Collection]
+pub struct CollectionIter<'a, T: Data> {
+    records: Option<RecordStream<Box<dyn Read + Send>>>,
+    error: Option<DBError<'a>>,
+    _data: PhantomData<T>,
+}
+
+impl<'a, T: Data> Iterator for CollectionIter<'a, T> {
+    type Item = Result<T, DBError<'a>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Some(e) = self.error.take() {
+            return Some(Result::Err(e));
+        }
+        let record = self.records.as_mut()?.next()?;
+        Some(match record {
+            Ok(record) => {
+                serde_json::from_slice(&record).map_err(|_| DBError::new("Could not parse data"))
+            }
+            Err(_) => Result::Err(DBError::new("Could not read collection")),
+        })
+    }
+}
+
+/// Typed handle to a collection verified to exist, created by
+/// [crate::db::TDatabase::open_collection_typed]. Operations skip the existence check and fail
+/// with `Collection was deleted` if the collection was deleted since it was opened
+pub struct TypedCollection<'a, T: Data> {
+    db: &'a Database,
+    name: String,
+    _data: PhantomData<T>,
+}
+
+impl<'a, T: Data> TypedCollection<'a, T> {
+    pub(crate) fn new(db: &'a Database, name: &str) -> TypedCollection<'a, T> {
+        TypedCollection {
+            db,
+            name: name.to_string(),
+            _data: PhantomData,
+        }
+    }
+
+    /// Name of the collection
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Inserts data into the collection
+    pub fn insert(&self, data: T) -> Result<(), DBError<'a>> {
+        let _lock = self.db.lock([self.name.as_str()]);
+        self.db.insert_into(
+            &self.name,
+            data,
+            self.db.read_opened_collection(&self.name)?,
+        )
+    }
+
+    /// Queries data from the collection
+    pub fn query(&self, uuid: &str) -> Result<T, DBError<'a>> {
+        self.list()?
+            .into_iter()
+            .find(|i| i.uuid() == uuid)
+            .ok_or(DBError::new("Data not found"))
+    }
+
+    /// Updates data in the collection
+    pub fn update(&self, data: T) -> Result<(), DBError<'a>> {
+        let _lock = self.db.lock([self.name.as_str()]);
+        self.db.update_in(
+            &self.name,
+            data,
+            self.db.read_opened_collection(&self.name)?,
+        )
+    }
+
+    /// Deletes data from the collection
+    pub fn delete(&self, uuid: &str) -> Result<(), DBError<'a>> {
+        let _lock = self.db.lock([self.name.as_str()]);
+        self.db.delete_from(&self.name, uuid, self.list()?)
+    }
+
+    /// Lists data from the collection
+    pub fn list(&self) -> Result<Vec<T>, DBError<'a>> {
+        self.db.read_opened_collection(&self.name)
+    }
+}
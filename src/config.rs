@@ -0,0 +1,67 @@
+use serde::{Deserialize, Serialize};
+
+/// Per-collection settings, stored in the collection metadata sidecar
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct CollectionConfig {
+    /// Keeps inserted records ordered by their uuid instead of insertion order
+    #[serde(default)]
+    pub sorted: bool,
+    /// Maximum number of records the collection may grow to, writes that do not add records are
+    /// allowed when the collection is already above it
+    #[serde(default)]
+    pub max_records: Option<usize>,
+    /// Json key holding the record id in typeless operations, [ID_KEY] if unset. Changing it
+    /// does not rewrite records already stored under the previous key
+    #[serde(default)]
+    pub id_key: Option<String>,
+    /// Keeps the collection when it is empty, see [crate::db::TDatabase::prune_empty_collections]
+    #[serde(default)]
+    pub persistent: bool,
+    /// Writes the collection file as pretty printed json, ignored without the `pretty` feature
+    #[serde(default)]
+    pub pretty: bool,
+}
+
+impl CollectionConfig {
+    /// Returns the json key holding the record id
+    pub fn id_key(&self) -> &str {
+        self.id_key.as_deref().unwrap_or(ID_KEY)
+    }
+
+    /// Serializes records the way the collection is configured to store them
+    pub(crate) fn to_json<T: Serialize>(&self, data: &[T]) -> String {
+        if cfg!(feature = "pretty") && self.pretty {
+            return serde_json::to_string_pretty(data).unwrap();
+        }
+        serde_json::to_string(data).unwrap()
+    }
+}
+
+/// Default json key holding the record id in stored records, see [CollectionConfig::id_key]
+pub const ID_KEY: &str = "uuid";
+
+/// Controls whether the id returned by [crate::Data::uuid] is written into stored records
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum IdPolicy {
+    /// Every stored record is a json object holding its id under the id key of its collection,
+    /// so typeless operations always find it. Records with a struct field of that name must hold
+    /// the same id, anything else is rejected. Types that do not serialize to an object or that
+    /// deny unknown fields can not be stored this way
+    Materialize,
+    /// Records are stored as serialized, typeless operations only find the id if it is a field
+    /// named after the id key of the collection
+    #[default]
+    AsSerialized,
+}
+
+/// How writers of the database exclude each other, see [crate::Database::with_lock_granularity]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum LockGranularity {
+    /// One lock per collection, writers of different collections run concurrently at the cost
+    /// of tracking the locked collections
+    #[default]
+    PerCollection,
+    /// One lock for the whole database, writers run one at a time. Cheapest when a single
+    /// collection is written or writes are rare
+    Global,
+}
@@ -1,9 +1,28 @@
 use serde::de::DeserializeOwned;
-use serde::{self, Serialize};
-use std::fs;
-use std::path::PathBuf;
+use serde::{self, Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::hash::{Hash, Hasher};
+use std::io::{self, Read};
+#[cfg(feature = "indexes")]
+use std::ops::{Bound, RangeBounds};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
 
-use crate::error::DBError;
+use crate::backend::{CollectionBackend, FileBackend};
+use crate::cache::{CacheEntry, CollectionCache};
+use crate::collection::{Collection, TypedCollection};
+use crate::config::{CollectionConfig, IdPolicy, LockGranularity};
+use crate::error::{DBError, ErrorScope};
+use crate::filesystem::{FileSystem, RetryPolicy, StdFileSystem};
+#[cfg(feature = "indexes")]
+use crate::index::Index;
+use crate::lock::{LockGuard, LockSet};
+use crate::migration::{CollectionMigration, Migration, MigrationDryRun, MigrationReport};
+use crate::stream::RecordStream;
+use crate::transaction::Transaction;
 
 /// Trait for data types that can be stored in the database, users must implement this trait for their data types
 pub trait Data: Serialize + DeserializeOwned + Clone {
@@ -12,21 +31,237 @@ pub trait Data: Serialize + DeserializeOwned + Clone {
 
 /// Trait for database types, [Database] implements this trait
 pub trait TDatabase {
-    fn connect(&mut self, path: PathBuf) -> Result<(), DBError>;
-    fn create_collection(&self, name: &str) -> Result<(), DBError>;
-    fn list_collections(&self) -> Result<Vec<String>, DBError>;
-    fn delete_collection(&self, name: &str) -> Result<(), DBError>;
-    fn rename_collection(&self, name: &str, new_name: &str) -> Result<(), DBError>;
-    fn insert_data<T: Data>(&self, collection: &str, data: T) -> Result<(), DBError>;
-    fn query_data<T: Data>(&mut self, collection: &str, uuid: &str) -> Result<T, DBError>;
-    fn update_data<T: Data>(&mut self, collection: &str, data: T) -> Result<(), DBError>;
-    fn delete_data<T: Data>(&mut self, collection: &str, uuid: &str) -> Result<(), DBError>;
-    fn list_data<T: Data>(&self, collection: &str) -> Result<Vec<T>, DBError>;
+    fn connect(&mut self, path: PathBuf) -> Result<(), DBError<'_>>;
+    fn create_collection(&self, name: &str) -> Result<(), DBError<'_>>;
+    fn list_collections(&self) -> Result<Vec<String>, DBError<'_>>;
+    fn delete_collection(&self, name: &str) -> Result<(), DBError<'_>>;
+    fn rename_collection(&self, name: &str, new_name: &str) -> Result<(), DBError<'_>>;
+    fn insert_data<T: Data>(&self, collection: &str, data: T) -> Result<(), DBError<'_>>;
+    fn query_data<T: Data>(&mut self, collection: &str, uuid: &str) -> Result<T, DBError<'_>>;
+    fn update_data<T: Data>(&mut self, collection: &str, data: T) -> Result<(), DBError<'_>>;
+    fn delete_data<T: Data>(&mut self, collection: &str, uuid: &str) -> Result<(), DBError<'_>>;
+    fn list_data<T: Data>(&self, collection: &str) -> Result<Vec<T>, DBError<'_>>;
+    fn register_migration<F>(&mut self, collection: &str, migration: F)
+    where
+        F: Fn(Value) -> Result<Value, DBError<'static>> + Send + Sync + 'static;
+    fn migrate(&self, collection: &str) -> Result<CollectionMigration, DBError<'_>>;
+    fn migrate_all(&self) -> Result<MigrationReport, DBError<'_>>;
+    fn migrate_dry_run(&self, collection: &str) -> Result<MigrationDryRun, DBError<'_>>;
+    fn join<L: Data, R: Data, K: Eq + Hash, O>(
+        &self,
+        left: &str,
+        right: &str,
+        left_key: impl Fn(&L) -> K,
+        right_key: impl Fn(&R) -> K,
+        combine: impl Fn(L, Option<R>) -> O,
+    ) -> Result<Vec<O>, DBError<'_>>;
+    fn configure_collection(&self, name: &str, config: CollectionConfig)
+        -> Result<(), DBError<'_>>;
+    fn register_canonicalizer<F>(&mut self, collection: &str, canonicalizer: F)
+    where
+        F: Fn(Value) -> Value + Send + Sync + 'static;
+    fn transfer_collection<'a, T: Data>(
+        &'a self,
+        name: &str,
+        dest: &'a Database,
+        dest_name: &str,
+        policy: MergePolicy,
+    ) -> Result<usize, DBError<'a>>;
+    fn find_or<'a, T: Data>(
+        &'a self,
+        collection: &str,
+        uuid: &str,
+        err: impl FnOnce() -> DBError<'a>,
+    ) -> Result<T, DBError<'a>>;
+    fn field_histogram(
+        &self,
+        collection: &str,
+        field: &str,
+    ) -> Result<HashMap<String, usize>, DBError<'_>>;
+    fn ids(&self, collection: &str) -> Result<Vec<String>, DBError<'_>>;
+    fn contains(&self, collection: &str, uuid: &str) -> Result<bool, DBError<'_>>;
+    #[cfg(feature = "indexes")]
+    fn create_index(&self, collection: &str, index: &str, field: &str) -> Result<(), DBError<'_>>;
+    #[cfg(feature = "indexes")]
+    fn query_by_index_range<T: Data, K: Ord + DeserializeOwned>(
+        &self,
+        collection: &str,
+        index: &str,
+        range: impl RangeBounds<K>,
+    ) -> Result<Vec<T>, DBError<'_>>;
+    fn count(&self, collection: &str) -> Result<usize, DBError<'_>>;
+    fn count_collections(&self) -> Result<usize, DBError<'_>>;
+    fn total_records(&self) -> Result<usize, DBError<'_>>;
+    fn record_summary(&self) -> Result<RecordSummary, DBError<'_>>;
+    fn compact(
+        &self,
+        collection: &str,
+        options: CompactOptions,
+    ) -> Result<CompactReport, DBError<'_>>;
+    fn open_collection_typed<T: Data>(
+        &self,
+        name: &str,
+    ) -> Result<TypedCollection<'_, T>, DBError<'_>>;
+    fn flush(&self) -> Result<(), DBError<'_>>;
+    fn find_indexed<T: Data>(
+        &self,
+        collection: &str,
+        uuid: &str,
+    ) -> Result<Option<(usize, T)>, DBError<'_>>;
+    fn mount_collection(
+        &self,
+        name: &str,
+        backend: impl CollectionBackend + 'static,
+    ) -> Result<(), DBError<'_>>;
+    fn upsert_many<T: Data>(
+        &self,
+        collection: &str,
+        data: Vec<T>,
+    ) -> Result<UpsertSummary, DBError<'_>>;
+    fn upsert_many_with<T: Data, F: Fn(&T, &T) -> T>(
+        &self,
+        collection: &str,
+        data: Vec<T>,
+        resolver: F,
+    ) -> Result<UpsertSummary, DBError<'_>>;
+    fn snapshot(&self) -> Result<SnapshotId, DBError<'_>>;
+    fn changed_since(&self, id: &SnapshotId) -> Result<bool, DBError<'_>>;
+    fn seed_from_dir(&self, dir: &Path, policy: MergePolicy) -> Result<SeedReport, DBError<'_>>;
+    fn dirty(&self) -> Vec<(String, String)>;
+    fn clear_dirty(&self);
+    fn prune_empty_collections(&self) -> Result<Vec<String>, DBError<'_>>;
+    fn insert_bytes(&self, collection: &str, id: &str, bytes: &[u8]) -> Result<(), DBError<'_>>;
+    fn get_bytes(&self, collection: &str, id: &str) -> Result<Vec<u8>, DBError<'_>>;
+    fn list_byte_ids(&self, collection: &str) -> Result<Vec<String>, DBError<'_>>;
+    fn aggregate<T: Data, A, F: FnMut(A, &T) -> A>(
+        &self,
+        collection: &str,
+        init: A,
+        f: F,
+    ) -> Result<A, DBError<'_>>;
 }
 
+/// Report returned by [TDatabase::seed_from_dir]
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SeedReport {
+    /// Seeded collections with the number of records written to each, sorted by name
+    pub collections: Vec<(String, usize)>,
+    /// Number of records skipped by [MergePolicy::Skip]
+    pub skipped: usize,
+}
+
+/// Hash of the database content returned by [TDatabase::snapshot], equal ids mean equal content
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct SnapshotId(u64);
+
+/// Report returned by [TDatabase::upsert_many] and [TDatabase::upsert_many_with]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct UpsertSummary {
+    /// Number of records with a new uuid
+    pub inserted: usize,
+    /// Number of records merged into a record with the same uuid
+    pub resolved: usize,
+}
+
+/// Options of [TDatabase::compact]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CompactOptions {
+    /// Merges records whose content, apart from their id, is identical. The first record is
+    /// kept and the others are removed
+    pub merge_duplicates: bool,
+}
+
+/// Report returned by [TDatabase::compact]
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CompactReport {
+    /// Number of records left in the collection
+    pub records: usize,
+    /// Merged duplicates as (kept id, removed id) pairs, duplicates without a stored id are
+    /// only listed in `merged_positions`
+    pub merged: Vec<(String, String)>,
+    /// Merged duplicates as (kept position, removed position) pairs of positions in the
+    /// collection before compacting
+    pub merged_positions: Vec<(usize, usize)>,
+}
+
+/// Record counts across the whole database, returned by [TDatabase::record_summary]
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RecordSummary {
+    /// Number of records in the readable collections
+    pub total: usize,
+    /// Collections that could not be read or parsed
+    pub unreadable: Vec<String>,
+}
+
+/// How records whose uuid already exists in the destination collection are handled
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MergePolicy {
+    /// Keeps the destination record
+    Skip,
+    /// Replaces the destination record
+    Overwrite,
+    /// Fails without writing anything
+    Fail,
+}
+
+/// Metadata stored next to a collection in its `.meta` sidecar file
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+struct CollectionMeta {
+    #[serde(default)]
+    version: u32,
+    #[serde(default)]
+    config: CollectionConfig,
+    #[serde(default)]
+    indexes: BTreeMap<String, StoredIndex>,
+}
+
+#[cfg(feature = "indexes")]
+type StoredIndex = Index;
+/// Indexes are kept as they are stored without the `indexes` feature
+#[cfg(not(feature = "indexes"))]
+type StoredIndex = Value;
+
+#[cfg(feature = "indexes")]
+impl CollectionMeta {
+    /// Returns true if the collection has indexes to keep up to date
+    fn has_indexes(&self) -> bool {
+        !self.indexes.is_empty()
+    }
+}
+
+/// Transforms a record into its canonical form before it is stored
+pub type Canonicalizer = Box<dyn Fn(Value) -> Value + Send + Sync>;
+
+/// Maps the name of a database file to its path relative to the database folder
+pub type PathTranslator = Box<dyn Fn(&str) -> PathBuf + Send + Sync>;
+
 /// Database struct used to interact with the database
 pub struct Database {
     path: PathBuf,
+    migrations: HashMap<String, Vec<Migration>>,
+    canonicalizers: HashMap<String, Canonicalizer>,
+    fs: Arc<dyn FileSystem>,
+    retry: RetryPolicy,
+    id_policy: IdPolicy,
+    path_translator: PathTranslator,
+    cache: Option<Mutex<CollectionCache>>,
+    backends: Mutex<HashMap<String, Arc<dyn CollectionBackend>>>,
+    validate_on_connect: bool,
+    connect_report: Option<RecordSummary>,
+    locks: LockSet,
+    dirty: Mutex<BTreeSet<(String, String)>>,
+}
+
+impl Default for Database {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Drop for Database {
+    fn drop(&mut self) {
+        let _ = self.flush();
+    }
 }
 
 impl Database {
@@ -34,41 +269,587 @@ impl Database {
     pub fn new() -> Database {
         Database {
             path: PathBuf::new(),
+            migrations: HashMap::new(),
+            canonicalizers: HashMap::new(),
+            fs: Arc::new(StdFileSystem),
+            retry: RetryPolicy::default(),
+            id_policy: IdPolicy::default(),
+            path_translator: Box::new(|file: &str| PathBuf::from(file)),
+            cache: None,
+            backends: Mutex::new(HashMap::new()),
+            validate_on_connect: false,
+            connect_report: None,
+            locks: LockSet::new(LockGranularity::default()),
+            dirty: Mutex::new(BTreeSet::new()),
         }
     }
 
-    /// Reads a collection from the database
-    fn read_collection<T: Data>(&self, collection: &str) -> Result<Vec<T>, DBError> {
-        // find collection file
+    /// Stores the database on the given file system instead of [StdFileSystem]
+    pub fn with_file_system(mut self, fs: impl FileSystem + 'static) -> Database {
+        self.fs = Arc::new(fs);
+        self
+    }
+
+    /// Retries reads and writes failing with a transient io error up to `retries` times,
+    /// waiting `backoff` before the first retry and doubling the wait every time
+    pub fn with_retry(mut self, retries: u32, backoff: Duration) -> Database {
+        self.retry = RetryPolicy { retries, backoff };
+        self
+    }
+
+    /// Sets whether record ids are written into stored records, see [IdPolicy]
+    pub fn with_id_policy(mut self, id_policy: IdPolicy) -> Database {
+        self.id_policy = id_policy;
+        self
+    }
+
+    /// Maps database file names, e.g. `users.json` or `users.meta`, to custom paths relative to
+    /// the database folder, missing parent directories are created.
+    ///
+    /// Collections can only be listed from a single directory: [TDatabase::list_collections]
+    /// lists the `.json` files of the directory the translator maps an empty name to. Layouts
+    /// spreading collection files over several directories, e.g. `users/users.json`, work for
+    /// every operation but listing and the operations built on it: [TDatabase::count_collections],
+    /// [TDatabase::total_records], [TDatabase::record_summary],
+    /// [TDatabase::prune_empty_collections], [TDatabase::snapshot] and
+    /// [Database::with_validate_on_connect]
+    pub fn with_path_translator<F>(mut self, translator: F) -> Database
+    where
+        F: Fn(&str) -> PathBuf + Send + Sync + 'static,
+    {
+        self.path_translator = Box::new(translator);
+        self
+    }
+
+    /// Returns the path of a database file, see [Database::with_path_translator]
+    fn resolve(&self, file: &str) -> PathBuf {
+        self.path.join((self.path_translator)(file))
+    }
+
+    /// Keeps the records of up to `capacity` collections in memory and buffers their writes.
+    /// The least recently used collection is written to disk when it is evicted, buffered
+    /// writes of the remaining ones are written by [TDatabase::flush] or when the database is
+    /// dropped. Buffered writes that fail stay cached and are retried by the next flush
+    pub fn with_collection_cache(mut self, capacity: usize) -> Database {
+        self.cache = Some(Mutex::new(CollectionCache::new(capacity)));
+        self
+    }
+
+    /// Parses every collection when connecting, [TDatabase::connect] fails if any of them is
+    /// unreadable and [Database::connect_report] lists them. A failed connect keeps the
+    /// previous database folder. Collections are only read when used by default
+    pub fn with_validate_on_connect(mut self, validate: bool) -> Database {
+        self.validate_on_connect = validate;
+        self
+    }
+
+    /// Returns the collections validated by the last [TDatabase::connect], `None` unless
+    /// [Database::with_validate_on_connect] is enabled
+    pub fn connect_report(&self) -> Option<&RecordSummary> {
+        self.connect_report.as_ref()
+    }
+
+    /// Fully parses every collection, counting the records of those that are readable
+    fn validate_collections(&self) -> RecordSummary {
+        let mut summary = RecordSummary::default();
+        let collections = match self.list_collections() {
+            Ok(collections) => collections,
+            Err(_) => return summary,
+        };
+        for collection in collections {
+            match self.read_collection::<Value>(&collection) {
+                Ok(c) => summary.total += c.len(),
+                Err(_) => summary.unreadable.push(collection),
+            }
+        }
+        summary.unreadable.sort();
+        summary
+    }
+
+    /// Sets how concurrent writers exclude each other, see [LockGranularity]. Operations that
+    /// read and rewrite a collection, like [TDatabase::insert_data], hold the lock of that
+    /// collection until they are done. [LockGranularity::PerCollection] lets writers of different
+    /// collections run in parallel but keeps a set of locked collection names,
+    /// [LockGranularity::Global] serializes every writer behind a single lock
+    pub fn with_lock_granularity(mut self, granularity: LockGranularity) -> Database {
+        self.locks = LockSet::new(granularity);
+        self
+    }
+
+    /// Locks the given collections for a read-modify-write, see [Database::with_lock_granularity]
+    pub(crate) fn lock<'n>(&self, collections: impl IntoIterator<Item = &'n str>) -> LockGuard<'_> {
+        self.locks.lock(collections)
+    }
+
+    /// Tags every [DBError] created on this thread with `context` until the returned guard is
+    /// dropped, see [DBError::context]
+    pub fn scope(&self, context: &str) -> ErrorScope {
+        ErrorScope::new(context)
+    }
+
+    /// Starts a transaction on a collection, its changes are buffered until
+    /// [Transaction::commit] and discarded by default if it is dropped without committing, see
+    /// [crate::transaction::FlushOnDrop]
+    pub fn transaction<T: Data>(&self, name: &str) -> Result<Transaction<'_, T>, DBError<'_>> {
+        if !self.collection_exists(name) {
+            return Result::Err(DBError::new("Collection does not exist"));
+        }
+        let base: Vec<Value> = self.read_opened_collection(name)?;
+        let records = serde_json::from_value(Value::Array(base.clone()))
+            .map_err(|_| DBError::new("Could not parse collection"))?;
+        Result::Ok(Transaction::new(self, name, base, records))
+    }
+
+    /// Returns a typed handle to a collection
+    pub fn collection<T: Data>(&self, name: &str) -> Collection<'_, T> {
+        Collection::new(self, name)
+    }
+
+    /// Opens a collection file for streaming reads
+    pub(crate) fn open_collection_reader(
+        &self,
+        collection: &str,
+    ) -> Result<Box<dyn Read + Send>, DBError<'_>> {
+        if let Some(backend) = self.backend(collection) {
+            let records = serde_json::to_vec(&backend.read_all()?).unwrap();
+            return Result::Ok(Box::new(io::Cursor::new(records)));
+        }
+        let collection_path = self.collection_path(collection);
+        if !self.fs.exists(&collection_path) {
+            return Result::Err(DBError::new("Collection does not exist"));
+        }
+        self.uncache(collection)?;
+        self.file_backend(collection).open()
+    }
+
+    /// Reads a file, retrying transient errors
+    fn read_file(&self, path: &Path) -> io::Result<String> {
+        self.retry.run(|| self.fs.read_to_string(path))
+    }
+
+    /// Writes a file, retrying transient errors
+    fn write_file(&self, path: &Path, contents: &str) -> io::Result<()> {
+        self.retry.run(|| self.fs.write(path, contents.as_bytes()))
+    }
+
+    /// Returns the backend storing a collection that is not mounted in its file
+    fn file_backend(&self, collection: &str) -> FileBackend {
+        FileBackend::new(self.fs.clone(), self.collection_path(collection)).with_retry(self.retry)
+    }
+
+    /// Returns the backend a collection is mounted on, if any
+    fn backend(&self, collection: &str) -> Option<Arc<dyn CollectionBackend>> {
+        self.backends
+            .lock()
+            .unwrap()
+            .get(&collection.to_lowercase())
+            .cloned()
+    }
+
+    /// Checks if a collection exists, either mounted or as a file
+    fn collection_exists(&self, collection: &str) -> bool {
+        self.backend(collection).is_some() || self.fs.exists(&self.collection_path(collection))
+    }
+
+    /// Returns the path of a collection file
+    fn collection_path(&self, collection: &str) -> PathBuf {
         let mut collection = collection.to_lowercase();
         collection.push_str(".json");
-        let collection_path = self.path.join(collection);
-        if !collection_path.exists() {
-            return Result::Err(DBError("Collection does not exist"));
+        self.resolve(&collection)
+    }
+
+    /// Returns the path of a collection metadata sidecar file
+    fn meta_path(&self, collection: &str) -> PathBuf {
+        let mut collection = collection.to_lowercase();
+        collection.push_str(".meta");
+        self.resolve(&collection)
+    }
+
+    /// Creates the directory a database file is stored in if it does not exist
+    fn create_parent_dir(&self, path: &Path) -> Result<(), DBError<'_>> {
+        match path.parent() {
+            Some(dir) if !self.fs.exists(dir) && self.fs.create_dir_all(dir).is_err() => {
+                Result::Err(DBError::new("Could not create directory"))
+            }
+            _ => Result::Ok(()),
+        }
+    }
+
+    /// Returns the directory holding the records of a byte collection, one file per record
+    fn bytes_path(&self, collection: &str) -> PathBuf {
+        let mut collection = collection.to_lowercase();
+        collection.push_str(".bytes");
+        self.resolve(&collection)
+    }
+
+    /// Returns the path of a byte record, ids must be usable as file names
+    fn byte_record_path(&self, collection: &str, id: &str) -> Result<PathBuf, DBError<'_>> {
+        if id.is_empty() || id == "." || id == ".." || id.contains(['/', '\\']) {
+            return Result::Err(DBError::new("Invalid byte record id"));
+        }
+        Result::Ok(self.bytes_path(collection).join(id))
+    }
+
+    /// Lists the byte collections stored next to the collection files, sorted
+    fn list_byte_collections(&self) -> Result<Vec<String>, DBError<'_>> {
+        let dir = self.resolve("");
+        if !self.fs.exists(&dir) {
+            return Result::Ok(Vec::new());
+        }
+        let mut collections: Vec<String> = self
+            .fs
+            .read_subdirs(&dir)
+            .map_err(|_| DBError::new("Could not list collections"))?
+            .iter()
+            .filter(|d| d.extension().unwrap_or_default() == "bytes")
+            .filter_map(|d| d.file_stem()?.to_str().map(|d| d.to_string()))
+            .collect();
+        collections.sort();
+        Result::Ok(collections)
+    }
+
+    /// Reads a collection from the database
+    fn read_collection<T: DeserializeOwned>(
+        &self,
+        collection: &str,
+    ) -> Result<Vec<T>, DBError<'_>> {
+        if !self.collection_exists(collection) {
+            return Result::Err(DBError::new("Collection does not exist"));
+        }
+        self.read_opened_collection(collection)
+    }
+
+    /// Reads a collection known to exist, a missing collection file means it was deleted since
+    pub(crate) fn read_opened_collection<T: DeserializeOwned>(
+        &self,
+        collection: &str,
+    ) -> Result<Vec<T>, DBError<'_>> {
+        if let Some(backend) = self.backend(collection) {
+            return serde_json::from_value(Value::Array(backend.read_all()?))
+                .map_err(|_| DBError::new("Could not parse collection"));
         }
-        // read collection file
-        let r = fs::read_to_string(&collection_path);
-        if r.is_err() {
-            return Result::Err(DBError("Could not read collection"));
+        let cache = match &self.cache {
+            Some(cache) => cache,
+            None => {
+                let records = self.file_backend(collection).read_all()?;
+                return serde_json::from_value(Value::Array(records))
+                    .map_err(|_| DBError::new("Could not parse collection"));
+            }
+        };
+        let cached = cache
+            .lock()
+            .unwrap()
+            .get(&collection.to_lowercase())
+            .map(|e| e.records.clone());
+        let records = match cached {
+            Some(records) => records,
+            None => {
+                let records = self.file_backend(collection).read_all()?;
+                let mut cache = cache.lock().unwrap();
+                // a write cached since the file was read wins over its content
+                match cache.get(&collection.to_lowercase()) {
+                    Some(entry) => entry.records.clone(),
+                    None => {
+                        let evicted = cache.put(&collection.to_lowercase(), records.clone(), false);
+                        self.flush_entries(&mut cache, evicted)?;
+                        records
+                    }
+                }
+            }
+        };
+        serde_json::from_value(Value::Array(records))
+            .map_err(|_| DBError::new("Could not parse collection"))
+    }
+
+    /// Writes the buffered records of evicted cache entries to disk. Every entry is tried, those
+    /// that could not be written are put back in the cache and the first error is returned. The
+    /// cache stays locked until the entries are written, so no reader sees a collection missing
+    /// from the cache while its file is still outdated
+    fn flush_entries(
+        &self,
+        cache: &mut CollectionCache,
+        entries: Vec<CacheEntry>,
+    ) -> Result<(), DBError<'_>> {
+        let mut result = Result::Ok(());
+        for entry in entries.into_iter().filter(|e| e.dirty) {
+            let written = self.read_meta(&entry.name).and_then(|meta| {
+                self.file_backend(&entry.name)
+                    .with_config(meta.config)
+                    .write_all(entry.records.clone())
+            });
+            if let Err(e) = written {
+                cache.restore(entry);
+                if result.is_ok() {
+                    result = Result::Err(e);
+                }
+            }
+        }
+        result
+    }
+
+    /// Removes a collection from the cache, writing its buffered records to disk
+    fn uncache(&self, collection: &str) -> Result<(), DBError<'_>> {
+        if let Some(cache) = &self.cache {
+            let mut cache = cache.lock().unwrap();
+            let entry = cache.remove(&collection.to_lowercase());
+            self.flush_entries(&mut cache, entry.into_iter().collect())?;
+        }
+        Result::Ok(())
+    }
+
+    #[cfg(feature = "indexes")]
+    /// Rebuilds the indexes of a collection from its records
+    fn update_indexes(
+        &self,
+        collection: &str,
+        meta: &mut CollectionMeta,
+        records: &[Value],
+    ) -> Result<(), DBError<'_>> {
+        if meta.has_indexes() {
+            let id_key = meta.config.id_key().to_string();
+            for index in meta.indexes.values_mut() {
+                *index = Index::build(&index.field, records, |r| {
+                    r.get(&id_key).and_then(record_id)
+                });
+            }
+            return self.write_meta(collection, meta);
         }
-        let r = r.unwrap();
-        let collection_data: Vec<T> = serde_json::from_str(&r).unwrap();
-        Result::Ok(collection_data)
+        Result::Ok(())
+    }
+
+    #[cfg(not(feature = "indexes"))]
+    /// Indexes are only maintained with the `indexes` feature, stored ones are left unchanged
+    fn update_indexes(
+        &self,
+        _collection: &str,
+        _meta: &mut CollectionMeta,
+        _records: &[Value],
+    ) -> Result<(), DBError<'_>> {
+        Result::Ok(())
     }
 
     /// Writes data to a collection in the database
-    fn write_collection<T: Data>(&self, collection: &str, data: Vec<T>) -> Result<(), DBError> {
-        // find collection file
-        let mut collection = collection.to_lowercase();
-        collection.push_str(".json");
-        let collection_path = self.path.join(collection);
-        if !collection_path.exists() {
-            return Result::Err(DBError("Collection does not exist"));
+    fn write_collection<T: Serialize>(
+        &self,
+        collection: &str,
+        data: Vec<T>,
+    ) -> Result<(), DBError<'_>> {
+        if !self.collection_exists(collection) {
+            return Result::Err(DBError::new("Collection does not exist"));
+        }
+        let mut meta = self.read_meta(collection)?;
+        let config = &meta.config;
+        // writes keeping or shrinking the size of a collection above its cap are allowed
+        if config.max_records.is_some_and(|max| data.len() > max) {
+            let stored = self
+                .read_opened_collection::<serde::de::IgnoredAny>(collection)
+                .map_or(0, |c| c.len());
+            if data.len() > stored {
+                return Result::Err(DBError::new("Collection is full"));
+            }
+        }
+        let records: Vec<Value> = data
+            .iter()
+            .map(|i| serde_json::to_value(i).unwrap())
+            .collect();
+        // keep indexes up to date
+        self.update_indexes(collection, &mut meta, &records)?;
+        // write to the mounted backend
+        if let Some(backend) = self.backend(collection) {
+            return backend.write_all(records);
+        }
+        // buffer the write in the cache
+        if let Some(cache) = &self.cache {
+            let mut cache = cache.lock().unwrap();
+            let evicted = cache.put(&collection.to_lowercase(), records, true);
+            return self.flush_entries(&mut cache, evicted);
         }
         // write collection file
-        let w = fs::write(collection_path, serde_json::to_string(&data).unwrap());
+        self.file_backend(collection)
+            .with_config(meta.config)
+            .write_all(records)
+    }
+
+    /// Writes typed data to a collection, applying the [IdPolicy] of the database
+    fn write_data<T: Data>(&self, collection: &str, data: Vec<T>) -> Result<(), DBError<'_>> {
+        if self.id_policy == IdPolicy::AsSerialized {
+            return self.write_collection(collection, data);
+        }
+        let meta = self.read_meta(collection)?;
+        let id_key = meta.config.id_key();
+        let mut records = Vec::with_capacity(data.len());
+        for i in data {
+            let uuid = i.uuid();
+            let mut record = serde_json::to_value(i).unwrap();
+            let object = match record.as_object_mut() {
+                Some(object) => object,
+                None => return Result::Err(DBError::new("Data is not a json object")),
+            };
+            match object.get(id_key) {
+                Some(id) if record_id(id).as_deref() != Some(&uuid) => {
+                    return Result::Err(DBError::new("Data id field does not match its uuid"));
+                }
+                Some(_) => {}
+                None => {
+                    object.insert(id_key.to_string(), Value::String(uuid));
+                }
+            }
+            records.push(record);
+        }
+        self.write_collection(collection, records)
+    }
+
+    /// Inserts data into the already read records of a collection
+    pub(crate) fn insert_into<T: Data>(
+        &self,
+        collection: &str,
+        data: T,
+        mut c: Vec<T>,
+    ) -> Result<(), DBError<'_>> {
+        let uuid = self.insert_record(collection, data, &mut c)?;
+        self.write_data(collection, c)?;
+        self.mark_dirty(collection, [uuid]);
+        Result::Ok(())
+    }
+
+    /// Updates data in the already read records of a collection
+    pub(crate) fn update_in<T: Data>(
+        &self,
+        collection: &str,
+        data: T,
+        mut c: Vec<T>,
+    ) -> Result<(), DBError<'_>> {
+        let uuid = self.update_record(collection, data, &mut c)?;
+        self.write_data(collection, c)?;
+        self.mark_dirty(collection, [uuid]);
+        Result::Ok(())
+    }
+
+    /// Deletes data from the already read records of a collection
+    pub(crate) fn delete_from<T: Data>(
+        &self,
+        collection: &str,
+        uuid: &str,
+        mut c: Vec<T>,
+    ) -> Result<(), DBError<'_>> {
+        self.delete_record(uuid, &mut c)?;
+        self.write_data(collection, c)?;
+        self.mark_dirty(collection, [uuid.to_string()]);
+        Result::Ok(())
+    }
+
+    /// Inserts data into records of a collection without writing them, returns its uuid
+    pub(crate) fn insert_record<T: Data>(
+        &self,
+        collection: &str,
+        data: T,
+        c: &mut Vec<T>,
+    ) -> Result<String, DBError<'_>> {
+        let data = self.canonicalize(collection, data)?;
+        let uuid = data.uuid();
+        if c.iter().any(|i| i.uuid() == uuid) {
+            return Result::Err(DBError::new("Data already exists"));
+        }
+        if self.read_meta(collection)?.config.sorted {
+            let i = c.partition_point(|i| i.uuid() < uuid);
+            c.insert(i, data);
+        } else {
+            c.push(data);
+        }
+        Result::Ok(uuid)
+    }
+
+    /// Updates data in records of a collection without writing them, returns its uuid
+    pub(crate) fn update_record<T: Data>(
+        &self,
+        collection: &str,
+        data: T,
+        c: &mut [T],
+    ) -> Result<String, DBError<'_>> {
+        let data = self.canonicalize(collection, data)?;
+        let uuid = data.uuid();
+        match c.iter_mut().find(|i| i.uuid() == uuid) {
+            Some(i) => *i = data,
+            None => return Result::Err(DBError::new("Data not found")),
+        }
+        Result::Ok(uuid)
+    }
+
+    /// Deletes data from records of a collection without writing them
+    pub(crate) fn delete_record<T: Data>(
+        &self,
+        uuid: &str,
+        c: &mut Vec<T>,
+    ) -> Result<(), DBError<'_>> {
+        match c.iter().position(|i| i.uuid() == uuid) {
+            Some(i) => {
+                c.remove(i);
+                Result::Ok(())
+            }
+            None => Result::Err(DBError::new("Data not found")),
+        }
+    }
+
+    /// Writes the records of a committed [Transaction] and marks the records it changed dirty,
+    /// fails if the stored records are no longer the `base` the transaction started from
+    pub(crate) fn commit_records<T: Data>(
+        &self,
+        collection: &str,
+        base: &[Value],
+        c: Vec<T>,
+        changed: impl IntoIterator<Item = String>,
+    ) -> Result<(), DBError<'_>> {
+        let _lock = self.lock([collection]);
+        let current: Vec<Value> = self.read_opened_collection(collection)?;
+        if current != base {
+            return Result::Err(DBError::new(
+                "Collection changed since the transaction started",
+            ));
+        }
+        self.write_data(collection, c)?;
+        self.mark_dirty(collection, changed);
+        Result::Ok(())
+    }
+
+    /// Remembers records changed by this process, see [TDatabase::dirty]
+    fn mark_dirty(&self, collection: &str, ids: impl IntoIterator<Item = String>) {
+        let collection = collection.to_lowercase();
+        let mut dirty = self.dirty.lock().unwrap();
+        dirty.extend(ids.into_iter().map(|id| (collection.clone(), id)));
+    }
+
+    /// Passes data through the canonicalizer registered for a collection, if any
+    fn canonicalize<T: Data>(&self, collection: &str, data: T) -> Result<T, DBError<'_>> {
+        match self.canonicalizers.get(&collection.to_lowercase()) {
+            Some(canonicalizer) => {
+                serde_json::from_value(canonicalizer(serde_json::to_value(data).unwrap()))
+                    .map_err(|_| DBError::new("Canonicalized data does not match its type"))
+            }
+            None => Result::Ok(data),
+        }
+    }
+
+    /// Reads the metadata of a collection, collections without a sidecar get the default metadata
+    fn read_meta(&self, collection: &str) -> Result<CollectionMeta, DBError<'_>> {
+        let meta_path = self.meta_path(collection);
+        if !self.fs.exists(&meta_path) {
+            return Result::Ok(CollectionMeta::default());
+        }
+        self.read_file(&meta_path)
+            .ok()
+            .and_then(|r| serde_json::from_str(&r).ok())
+            .ok_or(DBError::new("Could not read collection metadata"))
+    }
+
+    /// Writes the metadata of a collection to its sidecar
+    fn write_meta(&self, collection: &str, meta: &CollectionMeta) -> Result<(), DBError<'_>> {
+        self.create_parent_dir(&self.meta_path(collection))?;
+        let w = self.write_file(
+            &self.meta_path(collection),
+            &serde_json::to_string(meta).unwrap(),
+        );
         if w.is_err() {
-            return Result::Err(DBError("Could not write collection"));
+            return Result::Err(DBError::new("Could not write collection metadata"));
         }
         Result::Ok(())
     }
@@ -78,200 +859,2520 @@ impl TDatabase for Database {
     /// Connects to the database, creates the database if it does not exist
     /// # Arguments
     /// * `path` - Path to the database folder
-    fn connect(&mut self, path: PathBuf) -> Result<(), DBError> {
+    fn connect(&mut self, path: PathBuf) -> Result<(), DBError<'_>> {
         // check existence of folder path
-        if path.exists() {
+        if self.fs.exists(&path) {
             // check if path is a directory
-            if !path.is_dir() {
-                return Result::Err(DBError("Path is not a directory"));
+            if !self.fs.is_dir(&path) {
+                return Result::Err(DBError::new("Path is not a directory"));
             }
         } else {
-            let r = fs::create_dir_all(&path);
+            let r = self.fs.create_dir_all(&path);
+            if r.is_err() {
+                return Result::Err(DBError::new("Could not create directory"));
+            }
+        }
+        // collections are validated at the new path, the previous one is kept on failure
+        let previous = std::mem::replace(&mut self.path, path);
+        self.connect_report = None;
+        if self.validate_on_connect {
+            let report = self.validate_collections();
+            let unreadable = !report.unreadable.is_empty();
+            self.connect_report = Some(report);
+            if unreadable {
+                self.path = previous;
+                return Result::Err(DBError::new("Some collections could not be read"));
+            }
+        }
+        Result::Ok(())
+    }
+
+    /// Creates a new collection in the database
+    fn create_collection(&self, name: &str) -> Result<(), DBError<'_>> {
+        // check if collection exists
+        let collection_path = self.collection_path(name);
+        if self.collection_exists(name) {
+            return Result::Err(DBError::new("Collection already exists"));
+        }
+        // create collection
+        self.create_parent_dir(&collection_path)?;
+        self.file_backend(name)
+            .write_all(Vec::new())
+            .map_err(|_| DBError::new("Could not create collection"))
+    }
+
+    /// Lists collection in the database, see [Database::with_path_translator] for the directory
+    /// collection files are listed from
+    fn list_collections(&self) -> Result<Vec<String>, DBError<'_>> {
+        let mut mounted: Vec<String> = self.backends.lock().unwrap().keys().cloned().collect();
+        mounted.sort();
+        let dir = self.resolve("");
+        if !self.fs.exists(&dir) {
+            return Result::Ok(mounted);
+        }
+        self.fs
+            .read_dir(&dir)
+            .map(|r| {
+                r.into_iter()
+                    .filter(|r| r.extension().unwrap_or_default() == "json")
+                    .map(|r| r.file_stem().unwrap().to_str().unwrap().to_string())
+                    .filter(|r| !mounted.contains(r))
+                    .chain(mounted.iter().cloned())
+                    .collect()
+            })
+            .map_err(|_| DBError::new("Could not list collections"))
+    }
+
+    /// Deletes a collection from the database
+    fn delete_collection(&self, name: &str) -> Result<(), DBError<'_>> {
+        let collection_path = self.collection_path(name);
+        let unmounted = self.backends.lock().unwrap().remove(&name.to_lowercase());
+        if unmounted.is_none() {
+            if !self.fs.exists(&collection_path) {
+                return Result::Err(DBError::new("Collection does not exist"));
+            }
+            if let Some(cache) = &self.cache {
+                cache.lock().unwrap().remove(&name.to_lowercase());
+            }
+            let r = self.fs.remove_file(&collection_path);
             if r.is_err() {
-                return Result::Err(DBError("Could not create directory"));
+                return Result::Err(DBError::new("Could not delete collection"));
+            }
+        }
+        let meta_path = self.meta_path(name);
+        if self.fs.exists(&meta_path) && self.fs.remove_file(&meta_path).is_err() {
+            return Result::Err(DBError::new("Could not delete collection metadata"));
+        }
+        Result::Ok(())
+    }
+
+    /// Inserts data into a collection in the database
+    fn insert_data<T: Data>(&self, collection: &str, data: T) -> Result<(), DBError<'_>> {
+        let _lock = self.lock([collection]);
+        self.insert_into(collection, data, self.read_collection(collection)?)
+    }
+
+    /// Queries data from a collection in the database
+    fn query_data<T: Data>(&mut self, collection: &str, uuid: &str) -> Result<T, DBError<'_>> {
+        let c: Vec<T> = self.read_collection(collection)?;
+        for i in &c {
+            if i.uuid() == uuid {
+                return Result::Ok(i.clone());
+            }
+        }
+        Result::Err(DBError::new("Data not found"))
+    }
+
+    /// Updates data in a collection in the database
+    fn update_data<T: Data>(&mut self, collection: &str, data: T) -> Result<(), DBError<'_>> {
+        let _lock = self.lock([collection]);
+        self.update_in(collection, data, self.read_collection(collection)?)
+    }
+
+    /// Deletes data from a collection in the database
+    fn delete_data<T: Data>(&mut self, collection: &str, uuid: &str) -> Result<(), DBError<'_>> {
+        let _lock = self.lock([collection]);
+        self.delete_from(collection, uuid, self.read_collection::<T>(collection)?)
+    }
+
+    /// Lists data from a collection in the database
+    fn list_data<T: Data>(&self, collection: &str) -> Result<Vec<T>, DBError<'_>> {
+        self.read_collection(collection)
+    }
+
+    /// Updates the name of a collection in the database
+    fn rename_collection(&self, name: &str, new_name: &str) -> Result<(), DBError<'_>> {
+        let collection_path = self.collection_path(name);
+        let new_collection_path = self.collection_path(new_name);
+        if !self.collection_exists(name) {
+            return Result::Err(DBError::new("Collection does not exist"));
+        }
+        if self.collection_exists(new_name) {
+            return Result::Err(DBError::new("Collection already exists"));
+        }
+        let mut backends = self.backends.lock().unwrap();
+        match backends.remove(&name.to_lowercase()) {
+            Some(backend) => {
+                backends.insert(new_name.to_lowercase(), backend);
+            }
+            None => {
+                drop(backends);
+                self.uncache(name)?;
+                self.create_parent_dir(&new_collection_path)?;
+                let r = self.fs.rename(&collection_path, &new_collection_path);
+                if r.is_err() {
+                    return Result::Err(DBError::new("Could not rename collection"));
+                }
             }
         }
-        self.path = path;
+        let meta_path = self.meta_path(name);
+        let new_meta_path = self.meta_path(new_name);
+        if self.fs.exists(&meta_path)
+            && (self.create_parent_dir(&new_meta_path).is_err()
+                || self.fs.rename(&meta_path, &new_meta_path).is_err())
+        {
+            return Result::Err(DBError::new("Could not rename collection metadata"));
+        }
         Result::Ok(())
     }
 
-    /// Creates a new collection in the database
-    fn create_collection(&self, name: &str) -> Result<(), DBError> {
-        let mut name = name.to_lowercase();
-        name.push_str(".json");
-        // check if collection exists
-        let collection_path = self.path.join(name);
-        if collection_path.exists() {
-            return Result::Err(DBError("Collection already exists"));
+    /// Registers the next migration step of a collection, steps run in registration order
+    fn register_migration<F>(&mut self, collection: &str, migration: F)
+    where
+        F: Fn(Value) -> Result<Value, DBError<'static>> + Send + Sync + 'static,
+    {
+        self.migrations
+            .entry(collection.to_lowercase())
+            .or_default()
+            .push(Box::new(migration));
+    }
+
+    /// Runs the registered migration steps of a collection from its recorded version up to the latest,
+    /// every step is written on its own so a failing step leaves the collection at the last good version
+    fn migrate(&self, collection: &str) -> Result<CollectionMigration, DBError<'_>> {
+        let _lock = self.lock([collection]);
+        let mut meta = self.read_meta(collection)?;
+        let mut report = CollectionMigration {
+            collection: collection.to_lowercase(),
+            from: meta.version,
+            to: meta.version,
+            error: None,
+        };
+        let steps = match self.migrations.get(&collection.to_lowercase()) {
+            Some(steps) => steps,
+            None => return Result::Ok(report),
+        };
+        for step in steps.iter().skip(meta.version as usize) {
+            let c: Vec<Value> = self.read_collection(collection)?;
+            let migrated: Result<Vec<Value>, DBError<'static>> = c.into_iter().map(step).collect();
+            match migrated {
+                Ok(migrated) => {
+                    let ids: Vec<String> = migrated
+                        .iter()
+                        .filter_map(|r| r.get(meta.config.id_key()).and_then(record_id))
+                        .collect();
+                    let mut seen = BTreeSet::new();
+                    if let Some(id) = ids.iter().find(|id| !seen.insert(*id)) {
+                        report.error = Some(format!("Duplicate id {}", id));
+                        break;
+                    }
+                    self.write_collection(collection, migrated)?;
+                    self.mark_dirty(collection, ids);
+                    meta.version += 1;
+                    self.write_meta(collection, &meta)?;
+                    report.to = meta.version;
+                }
+                Err(e) => {
+                    report.error = Some(e.0.to_string());
+                    break;
+                }
+            }
+        }
+        Result::Ok(report)
+    }
+
+    /// Migrates every collection with registered migration steps, see [TDatabase::migrate]
+    fn migrate_all(&self) -> Result<MigrationReport, DBError<'_>> {
+        let mut collections: Vec<&String> = self.migrations.keys().collect();
+        collections.sort();
+        let mut report = MigrationReport::default();
+        for collection in collections {
+            if !self.collection_exists(collection) {
+                continue;
+            }
+            report.collections.push(self.migrate(collection)?);
+        }
+        Result::Ok(report)
+    }
+
+    /// Runs the pending migration steps of a collection on every record without writing
+    /// anything, reporting the records that would fail [TDatabase::migrate], including records
+    /// sharing a stored id after a step, and warning about stored ids that would be lost
+    fn migrate_dry_run(&self, collection: &str) -> Result<MigrationDryRun, DBError<'_>> {
+        let meta = self.read_meta(collection)?;
+        let c: Vec<Value> = self.read_collection(collection)?;
+        let steps: &[Migration] = match self.migrations.get(&collection.to_lowercase()) {
+            Some(steps) => steps.get(meta.version as usize..).unwrap_or_default(),
+            None => &[],
+        };
+        let mut report = MigrationDryRun {
+            collection: collection.to_lowercase(),
+            from: meta.version,
+            to: meta.version + steps.len() as u32,
+            ..Default::default()
+        };
+        let id_key = meta.config.id_key();
+        // positions of the records holding each stored id, after each step
+        let mut seen: Vec<BTreeMap<String, Vec<usize>>> = vec![BTreeMap::new(); steps.len()];
+        let total = c.len();
+        for (position, record) in c.into_iter().enumerate() {
+            // ids derived by the rust type are not stored and can not be checked
+            let mut id = record.get(id_key).and_then(record_id);
+            let stored_id = id.is_some();
+            let mut migrated = record;
+            let mut failure = None;
+            for (step, seen) in steps.iter().zip(seen.iter_mut()) {
+                migrated = match step(migrated) {
+                    Ok(migrated) => migrated,
+                    Err(e) => {
+                        failure = Some(e.0.to_string());
+                        break;
+                    }
+                };
+                id = migrated.get(id_key).and_then(record_id);
+                if let Some(id) = &id {
+                    seen.entry(id.clone()).or_default().push(position);
+                }
+            }
+            match failure {
+                Some(e) => report.failures.push((position, e)),
+                None if stored_id && id.is_none() => report.lost_ids.push(position),
+                None => {}
+            }
+        }
+        // records sharing an id fail at the first step they do
+        let mut duplicates: BTreeMap<usize, String> = BTreeMap::new();
+        for (id, positions) in seen.iter().flatten() {
+            if positions.len() > 1 {
+                for position in positions {
+                    duplicates
+                        .entry(*position)
+                        .or_insert_with(|| format!("Duplicate id {}", id));
+                }
+            }
+        }
+        report.failures.extend(duplicates);
+        report.failures.sort();
+        report
+            .lost_ids
+            .retain(|p| report.failures.iter().all(|(f, _)| f != p));
+        report.converted = total - report.failures.len();
+        Result::Ok(report)
+    }
+
+    /// Left joins two collections in memory, every left record is combined with the first right
+    /// record sharing its key, or with `None` if there is no such record
+    fn join<L: Data, R: Data, K: Eq + Hash, O>(
+        &self,
+        left: &str,
+        right: &str,
+        left_key: impl Fn(&L) -> K,
+        right_key: impl Fn(&R) -> K,
+        combine: impl Fn(L, Option<R>) -> O,
+    ) -> Result<Vec<O>, DBError<'_>> {
+        let l: Vec<L> = self.read_collection(left)?;
+        let r: Vec<R> = self.read_collection(right)?;
+        let mut right_map: HashMap<K, R> = HashMap::new();
+        for i in r {
+            right_map.entry(right_key(&i)).or_insert(i);
+        }
+        Result::Ok(
+            l.into_iter()
+                .map(|i| {
+                    let matched = right_map.get(&left_key(&i)).cloned();
+                    combine(i, matched)
+                })
+                .collect(),
+        )
+    }
+
+    /// Stores the settings of a collection in its metadata sidecar
+    fn configure_collection(
+        &self,
+        name: &str,
+        config: CollectionConfig,
+    ) -> Result<(), DBError<'_>> {
+        if !self.collection_exists(name) {
+            return Result::Err(DBError::new("Collection does not exist"));
+        }
+        let mut meta = self.read_meta(name)?;
+        meta.config = config;
+        self.write_meta(name, &meta)
+    }
+
+    /// Registers a canonicalizer for a collection, inserted and updated data is stored in its
+    /// canonical form and uuid uniqueness is checked against that form
+    fn register_canonicalizer<F>(&mut self, collection: &str, canonicalizer: F)
+    where
+        F: Fn(Value) -> Value + Send + Sync + 'static,
+    {
+        self.canonicalizers
+            .insert(collection.to_lowercase(), Box::new(canonicalizer));
+    }
+
+    /// Copies the records of a collection into a collection of another database, the destination
+    /// collection is created if it does not exist, returns the number of records written
+    fn transfer_collection<'a, T: Data>(
+        &'a self,
+        name: &str,
+        dest: &'a Database,
+        dest_name: &str,
+        policy: MergePolicy,
+    ) -> Result<usize, DBError<'a>> {
+        let c: Vec<T> = self.read_collection(name)?;
+        let _lock = dest.lock([dest_name]);
+        if !dest.collection_exists(dest_name) {
+            dest.create_collection(dest_name)?;
+        }
+        let mut d: Vec<T> = dest.read_collection(dest_name)?;
+        let mut written = Vec::new();
+        for data in c {
+            let data = dest.canonicalize(dest_name, data)?;
+            let uuid = data.uuid();
+            match d.iter().position(|i| i.uuid() == data.uuid()) {
+                Some(i) => match policy {
+                    MergePolicy::Skip => continue,
+                    MergePolicy::Overwrite => d[i] = data,
+                    MergePolicy::Fail => return Result::Err(DBError::new("Data already exists")),
+                },
+                None => d.push(data),
+            }
+            written.push(uuid);
+        }
+        if dest.read_meta(dest_name)?.config.sorted {
+            d.sort_by_key(|i| i.uuid());
+        }
+        dest.write_data(dest_name, d)?;
+        let count = written.len();
+        dest.mark_dirty(dest_name, written);
+        Result::Ok(count)
+    }
+
+    /// Queries data from a collection in the database, returns the error built by `err` if the
+    /// data is not found
+    fn find_or<'a, T: Data>(
+        &'a self,
+        collection: &str,
+        uuid: &str,
+        err: impl FnOnce() -> DBError<'a>,
+    ) -> Result<T, DBError<'a>> {
+        let c: Vec<T> = self.read_collection(collection)?;
+        c.into_iter().find(|i| i.uuid() == uuid).ok_or_else(err)
+    }
+
+    /// Counts the distinct values of a top level field in a collection, strings are counted by
+    /// their content and other values by their json text, records without the field are skipped
+    fn field_histogram(
+        &self,
+        collection: &str,
+        field: &str,
+    ) -> Result<HashMap<String, usize>, DBError<'_>> {
+        let c: Vec<Value> = self.read_collection(collection)?;
+        let mut histogram = HashMap::new();
+        for value in c.iter().filter_map(|i| i.get(field)) {
+            let key = match value {
+                Value::String(s) => s.clone(),
+                v => v.to_string(),
+            };
+            *histogram.entry(key).or_insert(0) += 1;
+        }
+        Result::Ok(histogram)
+    }
+
+    /// Lists the ids of the records in a collection without knowing their type, they are read
+    /// from the key set by [CollectionConfig::id_key], see [IdPolicy]
+    fn ids(&self, collection: &str) -> Result<Vec<String>, DBError<'_>> {
+        let c: Vec<Value> = self.read_collection(collection)?;
+        let meta = self.read_meta(collection)?;
+        c.iter()
+            .map(|i| i.get(meta.config.id_key()).and_then(record_id))
+            .collect::<Option<Vec<String>>>()
+            .ok_or(DBError::new("Data has no id"))
+    }
+
+    /// Checks if a collection holds a record with the given id without knowing its type
+    fn contains(&self, collection: &str, uuid: &str) -> Result<bool, DBError<'_>> {
+        Result::Ok(self.ids(collection)?.iter().any(|i| i == uuid))
+    }
+
+    #[cfg(feature = "indexes")]
+    /// Creates an ordered index over a top level field, the index is kept up to date on every
+    /// write and records without the field are not indexed. Records are indexed by their stored
+    /// id, see [CollectionConfig::id_key], creating the index fails if a record has none
+    fn create_index(&self, collection: &str, index: &str, field: &str) -> Result<(), DBError<'_>> {
+        let c: Vec<Value> = self.read_collection(collection)?;
+        let mut meta = self.read_meta(collection)?;
+        if meta.indexes.contains_key(index) {
+            return Result::Err(DBError::new("Index already exists"));
+        }
+        if c.iter()
+            .any(|r| r.get(meta.config.id_key()).and_then(record_id).is_none())
+        {
+            return Result::Err(DBError::new("Data has no id"));
+        }
+        meta.indexes.insert(
+            index.to_string(),
+            Index::build(field, &c, |r| {
+                r.get(meta.config.id_key()).and_then(record_id)
+            }),
+        );
+        self.write_meta(collection, &meta)
+    }
+
+    #[cfg(feature = "indexes")]
+    /// Queries the records whose indexed field is in `range`, ordered by that field, index keys
+    /// that are not a `K` are skipped
+    fn query_by_index_range<T: Data, K: Ord + DeserializeOwned>(
+        &self,
+        collection: &str,
+        index: &str,
+        range: impl RangeBounds<K>,
+    ) -> Result<Vec<T>, DBError<'_>> {
+        let meta = self.read_meta(collection)?;
+        let index = match meta.indexes.get(index) {
+            Some(index) => index,
+            None => return Result::Err(DBError::new("Index does not exist")),
+        };
+        let ids: Vec<&String> = index
+            .entries
+            .iter()
+            .filter_map(|(key, id)| Some((K::deserialize(key).ok()?, id)))
+            .skip_while(|(key, _)| match range.start_bound() {
+                Bound::Included(start) => key < start,
+                Bound::Excluded(start) => key <= start,
+                Bound::Unbounded => false,
+            })
+            .take_while(|(key, _)| match range.end_bound() {
+                Bound::Included(end) => key <= end,
+                Bound::Excluded(end) => key < end,
+                Bound::Unbounded => true,
+            })
+            .map(|(_, id)| id)
+            .collect();
+        // only the matching records are deserialized
+        let id_key = meta.config.id_key();
+        let mut c: HashMap<String, Value> = self
+            .read_collection::<Value>(collection)?
+            .into_iter()
+            .filter_map(|r| Some((r.get(id_key).and_then(record_id)?, r)))
+            .collect();
+        ids.into_iter()
+            .filter_map(|id| c.remove(id))
+            .map(|r| {
+                serde_json::from_value(r).map_err(|_| DBError::new("Could not parse collection"))
+            })
+            .collect()
+    }
+
+    /// Counts the records of a collection without knowing their type or holding them in memory,
+    /// every record is still checked to be valid json
+    fn count(&self, collection: &str) -> Result<usize, DBError<'_>> {
+        let reader = self.open_collection_reader(collection)?;
+        let mut count = 0;
+        for r in RecordStream::new(reader) {
+            let valid = r
+                .ok()
+                .is_some_and(|r| serde_json::from_slice::<serde::de::IgnoredAny>(&r).is_ok());
+            if !valid {
+                return Result::Err(DBError::new("Could not parse collection"));
+            }
+            count += 1;
+        }
+        Result::Ok(count)
+    }
+
+    /// Counts the collections in the database
+    fn count_collections(&self) -> Result<usize, DBError<'_>> {
+        Result::Ok(self.list_collections()?.len())
+    }
+
+    /// Counts the records of every collection in the database, unreadable collections are skipped
+    fn total_records(&self) -> Result<usize, DBError<'_>> {
+        Result::Ok(self.record_summary()?.total)
+    }
+
+    /// Counts the records of every collection in the database and lists those that could not be read
+    fn record_summary(&self) -> Result<RecordSummary, DBError<'_>> {
+        let mut summary = RecordSummary::default();
+        for collection in self.list_collections()? {
+            match self.count(&collection) {
+                Ok(count) => summary.total += count,
+                Err(_) => summary.unreadable.push(collection),
+            }
+        }
+        summary.unreadable.sort();
+        Result::Ok(summary)
+    }
+
+    /// Rewrites a collection file and rebuilds its indexes, optionally merging duplicate records
+    fn compact(
+        &self,
+        collection: &str,
+        options: CompactOptions,
+    ) -> Result<CompactReport, DBError<'_>> {
+        let _lock = self.lock([collection]);
+        let c: Vec<Value> = self.read_collection(collection)?;
+        let meta = self.read_meta(collection)?;
+        let id_key = meta.config.id_key();
+        let mut report = CompactReport::default();
+        let mut records = Vec::with_capacity(c.len());
+        let mut seen: HashMap<String, (usize, Option<String>)> = HashMap::new();
+        for (position, record) in c.into_iter().enumerate() {
+            if options.merge_duplicates {
+                let id = record.get(id_key).and_then(record_id);
+                let mut content = record.clone();
+                if let Some(object) = content.as_object_mut() {
+                    object.remove(id_key);
+                }
+                let content = content.to_string();
+                match seen.get(&content) {
+                    Some((kept_position, kept)) => {
+                        report.merged_positions.push((*kept_position, position));
+                        if let (Some(kept), Some(id)) = (kept, id) {
+                            report.merged.push((kept.clone(), id));
+                        }
+                        continue;
+                    }
+                    None => {
+                        seen.insert(content, (position, id));
+                    }
+                }
+            }
+            records.push(record);
+        }
+        report.records = records.len();
+        self.write_collection(collection, records)?;
+        self.mark_dirty(collection, report.merged.iter().map(|(_, id)| id.clone()));
+        Result::Ok(report)
+    }
+
+    /// Opens a typed handle to a collection, checking once that the collection exists
+    fn open_collection_typed<T: Data>(
+        &self,
+        name: &str,
+    ) -> Result<TypedCollection<'_, T>, DBError<'_>> {
+        if !self.collection_exists(name) {
+            return Result::Err(DBError::new("Collection does not exist"));
+        }
+        Result::Ok(TypedCollection::new(self, name))
+    }
+
+    /// Writes the buffered records of every cached collection to disk
+    fn flush(&self) -> Result<(), DBError<'_>> {
+        if let Some(cache) = &self.cache {
+            let mut cache = cache.lock().unwrap();
+            let entries = cache.drain();
+            self.flush_entries(&mut cache, entries)?;
+        }
+        Result::Ok(())
+    }
+
+    /// Queries data and its zero based position in a collection, returns `None` if the data is
+    /// not found
+    fn find_indexed<T: Data>(
+        &self,
+        collection: &str,
+        uuid: &str,
+    ) -> Result<Option<(usize, T)>, DBError<'_>> {
+        let c: Vec<T> = self.read_collection(collection)?;
+        Result::Ok(c.into_iter().enumerate().find(|(_, i)| i.uuid() == uuid))
+    }
+
+    /// Mounts a collection on a custom backend instead of the [FileBackend] storing it in the
+    /// database folder, the mount lasts until the collection is deleted or the database is dropped
+    fn mount_collection(
+        &self,
+        name: &str,
+        backend: impl CollectionBackend + 'static,
+    ) -> Result<(), DBError<'_>> {
+        if self.collection_exists(name) {
+            return Result::Err(DBError::new("Collection already exists"));
+        }
+        self.backends
+            .lock()
+            .unwrap()
+            .insert(name.to_lowercase(), Arc::new(backend));
+        Result::Ok(())
+    }
+
+    /// Inserts or replaces many records in a single write, later records win over earlier ones
+    fn upsert_many<T: Data>(
+        &self,
+        collection: &str,
+        data: Vec<T>,
+    ) -> Result<UpsertSummary, DBError<'_>> {
+        self.upsert_many_with(collection, data, |_, new| new.clone())
+    }
+
+    /// Inserts many records in a single write, a record whose uuid is already stored or earlier
+    /// in the batch is replaced by `resolver(existing, new)`
+    fn upsert_many_with<T: Data, F: Fn(&T, &T) -> T>(
+        &self,
+        collection: &str,
+        data: Vec<T>,
+        resolver: F,
+    ) -> Result<UpsertSummary, DBError<'_>> {
+        let _lock = self.lock([collection]);
+        let mut c: Vec<T> = self.read_collection(collection)?;
+        let mut positions: HashMap<String, usize> =
+            c.iter().enumerate().map(|(i, d)| (d.uuid(), i)).collect();
+        let mut summary = UpsertSummary::default();
+        let mut ids = Vec::with_capacity(data.len());
+        for data in data {
+            let data = self.canonicalize(collection, data)?;
+            ids.push(data.uuid());
+            match positions.get(&data.uuid()) {
+                Some(&i) => {
+                    let resolved = resolver(&c[i], &data);
+                    if resolved.uuid() != data.uuid() {
+                        return Result::Err(DBError::new("Resolved data changed its uuid"));
+                    }
+                    c[i] = resolved;
+                    summary.resolved += 1;
+                }
+                None => {
+                    positions.insert(data.uuid(), c.len());
+                    c.push(data);
+                    summary.inserted += 1;
+                }
+            }
+        }
+        if self.read_meta(collection)?.config.sorted {
+            c.sort_by_key(|i| i.uuid());
+        }
+        self.write_data(collection, c)?;
+        self.mark_dirty(collection, ids);
+        Result::Ok(summary)
+    }
+
+    /// Hashes the records and metadata of every collection and the payloads of every byte
+    /// collection in the database
+    fn snapshot(&self) -> Result<SnapshotId, DBError<'_>> {
+        let mut hasher = DefaultHasher::new();
+        // the order of listed files depends on the file system
+        let mut collections = self.list_collections()?;
+        collections.sort();
+        for collection in collections {
+            let c: Vec<Value> = self.read_collection(&collection)?;
+            let meta = self.read_meta(&collection)?;
+            collection.hash(&mut hasher);
+            serde_json::to_string(&c).unwrap().hash(&mut hasher);
+            serde_json::to_string(&meta).unwrap().hash(&mut hasher);
+        }
+        for collection in self.list_byte_collections()? {
+            format!("{}.bytes", collection).hash(&mut hasher);
+            for id in self.list_byte_ids(&collection)? {
+                self.get_bytes(&collection, &id)?.hash(&mut hasher);
+                id.hash(&mut hasher);
+            }
+        }
+        Result::Ok(SnapshotId(hasher.finish()))
+    }
+
+    /// Returns true if the database content differs from the given snapshot
+    fn changed_since(&self, id: &SnapshotId) -> Result<bool, DBError<'_>> {
+        Result::Ok(self.snapshot()? != *id)
+    }
+
+    /// Imports every `.json` file of a directory into the collection named after its stem,
+    /// records are matched by their id. Nothing is written if a file is not a json array of
+    /// records with ids, if a collection would exceed its cap or if [MergePolicy::Fail] finds an
+    /// existing record. If writing a collection fails, the collections already seeded are
+    /// restored as best as possible
+    fn seed_from_dir(&self, dir: &Path, policy: MergePolicy) -> Result<SeedReport, DBError<'_>> {
+        let mut files = self
+            .fs
+            .read_dir(dir)
+            .map_err(|_| DBError::new("Could not read seed directory"))?;
+        files.retain(|f| f.extension().unwrap_or_default() == "json");
+        files.sort();
+        let names: Vec<String> = files
+            .iter()
+            .map(|f| f.file_stem().unwrap().to_str().unwrap().to_string())
+            .collect();
+        let _lock = self.lock(names.iter().map(|n| n.as_str()));
+        let mut report = SeedReport::default();
+        let mut seeded = Vec::new();
+        for (file, name) in files.iter().zip(names.iter().cloned()) {
+            let records: Vec<Value> = self
+                .read_file(file)
+                .ok()
+                .and_then(|r| serde_json::from_str(&r).ok())
+                .ok_or(DBError::new("Seed file is not a json array"))?;
+            let previous: Option<Vec<Value>> = if self.collection_exists(&name) {
+                Some(self.read_collection(&name)?)
+            } else {
+                None
+            };
+            let mut c = previous.clone().unwrap_or_default();
+            let meta = self.read_meta(&name)?;
+            let id_key = meta.config.id_key();
+            let mut written = Vec::new();
+            for record in records {
+                let id = record
+                    .get(id_key)
+                    .and_then(record_id)
+                    .ok_or(DBError::new("Seed record has no id"))?;
+                match c
+                    .iter()
+                    .position(|i| i.get(id_key).and_then(record_id).as_ref() == Some(&id))
+                {
+                    Some(i) => match policy {
+                        MergePolicy::Skip => {
+                            report.skipped += 1;
+                            continue;
+                        }
+                        MergePolicy::Overwrite => c[i] = record,
+                        MergePolicy::Fail => {
+                            return Result::Err(DBError::new("Data already exists"))
+                        }
+                    },
+                    None => c.push(record),
+                }
+                written.push(id);
+            }
+            if meta.config.sorted {
+                c.sort_by_key(|i| i.get(id_key).and_then(record_id));
+            }
+            let stored = previous.as_ref().map_or(0, |p| p.len());
+            if meta
+                .config
+                .max_records
+                .is_some_and(|max| c.len() > max && c.len() > stored)
+            {
+                return Result::Err(DBError::new("Collection is full"));
+            }
+            report.collections.push((name.clone(), written.len()));
+            seeded.push((name, previous, c, written));
+        }
+        for (i, (name, _, c, _)) in seeded.iter().enumerate() {
+            let w = if self.collection_exists(name) {
+                self.write_collection(name, c.clone())
+            } else {
+                self.create_collection(name)
+                    .and_then(|_| self.write_collection(name, c.clone()))
+            };
+            if let Err(e) = w {
+                // undo the collections seeded so far, the failed one included
+                for (name, previous, _, _) in &seeded[..=i] {
+                    let _ = match previous {
+                        Some(previous) => self.write_collection(name, previous.clone()),
+                        None if self.collection_exists(name) => self.delete_collection(name),
+                        None => Result::Ok(()),
+                    };
+                }
+                return Result::Err(e);
+            }
+        }
+        for (name, _, _, written) in seeded {
+            self.mark_dirty(&name, written);
+        }
+        Result::Ok(report)
+    }
+
+    /// Lists the (collection, uuid) pairs of the records changed by this process since it was
+    /// created or since [TDatabase::clear_dirty], sorted. Deleted records are listed too
+    fn dirty(&self) -> Vec<(String, String)> {
+        self.dirty.lock().unwrap().iter().cloned().collect()
+    }
+
+    /// Forgets the records listed by [TDatabase::dirty]
+    fn clear_dirty(&self) {
+        self.dirty.lock().unwrap().clear();
+    }
+
+    /// Deletes every collection without records, except those configured as
+    /// [CollectionConfig::persistent], and returns their names sorted. Collections with an
+    /// unreadable file or metadata sidecar are kept
+    fn prune_empty_collections(&self) -> Result<Vec<String>, DBError<'_>> {
+        let mut pruned = Vec::new();
+        for collection in self.list_collections()? {
+            let _lock = self.lock([collection.as_str()]);
+            if self
+                .read_meta(&collection)
+                .map_or(true, |m| m.config.persistent)
+            {
+                continue;
+            }
+            if self.count(&collection).is_ok_and(|count| count == 0) {
+                self.delete_collection(&collection)?;
+                pruned.push(collection);
+            }
+        }
+        pruned.sort();
+        Result::Ok(pruned)
+    }
+
+    /// Stores an opaque payload in a byte collection, byte collections keep one file per record
+    /// in a `<collection>.bytes` directory and are separate from json collections
+    fn insert_bytes(&self, collection: &str, id: &str, bytes: &[u8]) -> Result<(), DBError<'_>> {
+        let path = self.byte_record_path(collection, id)?;
+        let _lock = self.lock([collection]);
+        if self.fs.exists(&path) {
+            return Result::Err(DBError::new("Data already exists"));
+        }
+        let dir = self.bytes_path(collection);
+        if !self.fs.exists(&dir) && self.fs.create_dir_all(&dir).is_err() {
+            return Result::Err(DBError::new("Could not create directory"));
+        }
+        if self.retry.run(|| self.fs.write(&path, bytes)).is_err() {
+            return Result::Err(DBError::new("Could not write data"));
+        }
+        self.mark_dirty(collection, [id.to_string()]);
+        Result::Ok(())
+    }
+
+    /// Reads a payload stored by [TDatabase::insert_bytes]
+    fn get_bytes(&self, collection: &str, id: &str) -> Result<Vec<u8>, DBError<'_>> {
+        let path = self.byte_record_path(collection, id)?;
+        if !self.fs.exists(&path) {
+            return Result::Err(DBError::new("Data not found"));
+        }
+        self.retry
+            .run(|| {
+                let mut bytes = Vec::new();
+                self.fs.open(&path)?.read_to_end(&mut bytes)?;
+                Ok(bytes)
+            })
+            .map_err(|_| DBError::new("Could not read data"))
+    }
+
+    /// Lists the ids of a byte collection sorted, a collection without payloads has no ids
+    fn list_byte_ids(&self, collection: &str) -> Result<Vec<String>, DBError<'_>> {
+        let dir = self.bytes_path(collection);
+        if !self.fs.exists(&dir) {
+            return Result::Ok(Vec::new());
+        }
+        let mut ids: Vec<String> = self
+            .fs
+            .read_dir(&dir)
+            .map_err(|_| DBError::new("Could not list data"))?
+            .iter()
+            .filter_map(|f| f.file_name()?.to_str().map(|f| f.to_string()))
+            .collect();
+        ids.sort();
+        Result::Ok(ids)
+    }
+
+    /// Folds over the records of a collection in a single streaming pass, only one record of a
+    /// collection file is held in memory at a time. Mounted collections are read whole from
+    /// their backend first
+    fn aggregate<T: Data, A, F: FnMut(A, &T) -> A>(
+        &self,
+        collection: &str,
+        init: A,
+        mut f: F,
+    ) -> Result<A, DBError<'_>> {
+        let reader = self.open_collection_reader(collection)?;
+        let mut acc = init;
+        for r in RecordStream::new(reader) {
+            let record: T = r
+                .ok()
+                .and_then(|r| serde_json::from_slice(&r).ok())
+                .ok_or(DBError::new("Could not parse collection"))?;
+            acc = f(acc, &record);
+        }
+        Result::Ok(acc)
+    }
+}
+
+/// Returns the id held by a json value, ids are strings or numbers
+fn record_id(id: &Value) -> Option<String> {
+    match id {
+        Value::String(s) => Some(s.clone()),
+        Value::Number(n) => Some(n.to_string()),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use serde::Deserialize;
+    use std::fs;
+    use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+    use std::sync::{mpsc, Arc};
+    use tempfile::{tempdir, TempDir};
+
+    fn setup() -> (Database, TempDir) {
+        let mut db = Database::new();
+        let db_dir = tempdir().unwrap();
+        db.connect(db_dir.path().to_path_buf()).unwrap();
+        (db, db_dir)
+    }
+
+    #[test]
+    fn test_connect() {
+        let (_db, db_dir) = setup();
+        assert!(db_dir.path().exists());
+        assert!(db_dir.path().is_dir());
+    }
+
+    #[test]
+    fn test_create_collection() {
+        let (db, _db_dir) = setup();
+        db.create_collection("test").unwrap();
+        assert!(db.path.join("test.json").exists());
+        assert!(db.path.join("test.json").is_file());
+    }
+
+    #[test]
+    fn test_insert_data() {
+        #[derive(Debug, Serialize, Deserialize, Clone)]
+        struct TestData {
+            uuid: String,
+            name: String,
+        }
+        impl Data for TestData {
+            fn uuid(&self) -> String {
+                self.uuid.clone()
+            }
+        }
+        let (db, _db_dir) = setup();
+        db.create_collection("test").unwrap();
+        let data = TestData {
+            uuid: "test".to_string(),
+            name: "test".to_string(),
+        };
+        db.insert_data("test", data.clone()).unwrap();
+        let r: Vec<TestData> = db.read_collection("test").unwrap();
+        assert_eq!(r[0].uuid, data.uuid);
+    }
+
+    #[test]
+    fn test_query_data() {
+        #[derive(Debug, Serialize, Deserialize, Clone)]
+        struct TestData {
+            uuid: String,
+            name: String,
+        }
+        impl Data for TestData {
+            fn uuid(&self) -> String {
+                self.uuid.clone()
+            }
+        }
+        let (mut db, _db_dir) = setup();
+        db.create_collection("test").unwrap();
+        let data_1 = TestData {
+            uuid: "test".to_string(),
+            name: "test".to_string(),
+        };
+        db.insert_data("test", data_1.clone()).unwrap();
+        let data_2 = TestData {
+            uuid: "test2".to_string(),
+            name: "test2".to_string(),
+        };
+        db.insert_data("test", data_2.clone()).unwrap();
+        let r: TestData = db.query_data("test", "test").unwrap();
+        assert_eq!(r.uuid, data_1.uuid);
+        assert_eq!(r.name, data_1.name);
+    }
+
+    #[test]
+    fn test_delete_data() {
+        #[derive(Debug, Serialize, Deserialize, Clone)]
+        struct TestData {
+            uuid: String,
+            name: String,
+        }
+        impl Data for TestData {
+            fn uuid(&self) -> String {
+                self.uuid.clone()
+            }
+        }
+        let (mut db, _db_dir) = setup();
+        db.create_collection("test").unwrap();
+        let data_1 = TestData {
+            uuid: "test".to_string(),
+            name: "test".to_string(),
+        };
+        db.insert_data("test", data_1.clone()).unwrap();
+        let data_2 = TestData {
+            uuid: "test2".to_string(),
+            name: "test2".to_string(),
+        };
+        db.insert_data("test", data_2.clone()).unwrap();
+        db.delete_data::<TestData>("test", "test").unwrap();
+        let r: Vec<TestData> = db.read_collection("test").unwrap();
+        assert_eq!(r.len(), 1);
+        assert_eq!(r[0].uuid, data_2.uuid);
+    }
+
+    #[test]
+    fn test_update_data() {
+        #[derive(Debug, Serialize, Deserialize, Clone)]
+        struct TestData {
+            uuid: String,
+            name: String,
+        }
+        impl Data for TestData {
+            fn uuid(&self) -> String {
+                self.uuid.clone()
+            }
+        }
+        let (mut db, _db_dir) = setup();
+        db.create_collection("test").unwrap();
+        let data_1 = TestData {
+            uuid: "test".to_string(),
+            name: "test".to_string(),
+        };
+        db.insert_data("test", data_1.clone()).unwrap();
+        let data_2 = TestData {
+            uuid: "test".to_string(),
+            name: "test2".to_string(),
+        };
+        db.update_data("test", data_2.clone()).unwrap();
+        let r: Vec<TestData> = db.read_collection("test").unwrap();
+        assert_eq!(r.len(), 1);
+        assert_eq!(r[0].uuid, data_2.uuid);
+        assert_eq!(r[0].name, data_2.name);
+    }
+
+    #[test]
+    fn test_list_collections() {
+        let (db, _db_dir) = setup();
+        db.create_collection("test").unwrap();
+        db.create_collection("test2").unwrap();
+        let r: Vec<String> = db.list_collections().unwrap();
+        assert_eq!(r.len(), 2);
+    }
+
+    #[test]
+    fn test_delete_collection() {
+        let (db, _db_dir) = setup();
+        db.create_collection("test").unwrap();
+        db.delete_collection("test").unwrap();
+        assert!(!db.path.join("test.json").exists());
+    }
+
+    #[test]
+    fn test_rename_collection() {
+        let (db, _db_dir) = setup();
+        db.create_collection("test").unwrap();
+        db.rename_collection("test", "test2").unwrap();
+        assert!(!db.path.join("test.json").exists());
+        assert!(db.path.join("test2.json").exists());
+    }
+
+    #[test]
+    fn test_migrate_all() {
+        let (mut db, _db_dir) = setup();
+        db.create_collection("test").unwrap();
+        db.write_collection(
+            "test",
+            vec![serde_json::json!({"uuid": "test", "name": "test"})],
+        )
+        .unwrap();
+        db.register_migration("test", |mut v: Value| {
+            v["age"] = Value::from(1);
+            Ok(v)
+        });
+        db.register_migration("test", |mut v: Value| {
+            v["name"] = Value::from(v["name"].as_str().unwrap().to_uppercase());
+            Ok(v)
+        });
+        let report = db.migrate_all().unwrap();
+        assert!(report.is_ok());
+        assert_eq!(report.collections.len(), 1);
+        assert_eq!(report.collections[0].from, 0);
+        assert_eq!(report.collections[0].to, 2);
+        let r: Vec<Value> = db.read_collection("test").unwrap();
+        assert_eq!(r[0]["age"], 1);
+        assert_eq!(r[0]["name"], "TEST");
+        assert_eq!(db.read_meta("test").unwrap().version, 2);
+        // already at the latest version, nothing runs again
+        let report = db.migrate_all().unwrap();
+        assert_eq!(report.collections[0].from, 2);
+        assert_eq!(report.collections[0].to, 2);
+    }
+
+    #[test]
+    fn test_migrate_failure_keeps_last_good_version() {
+        let (mut db, _db_dir) = setup();
+        db.create_collection("test").unwrap();
+        db.write_collection("test", vec![serde_json::json!({"uuid": "test"})])
+            .unwrap();
+        db.register_migration("test", |mut v: Value| {
+            v["age"] = Value::from(1);
+            Ok(v)
+        });
+        db.register_migration("test", |_| Err(DBError::new("Migration failed")));
+        let report = db.migrate_all().unwrap();
+        assert!(!report.is_ok());
+        assert_eq!(report.collections[0].to, 1);
+        assert_eq!(
+            report.collections[0].error.as_deref(),
+            Some("Migration failed")
+        );
+        let r: Vec<Value> = db.read_collection("test").unwrap();
+        assert_eq!(r[0]["age"], 1);
+        assert_eq!(db.read_meta("test").unwrap().version, 1);
+    }
+
+    #[test]
+    fn test_join() {
+        #[derive(Debug, Serialize, Deserialize, Clone)]
+        struct User {
+            uuid: String,
+            name: String,
+        }
+        impl Data for User {
+            fn uuid(&self) -> String {
+                self.uuid.clone()
+            }
+        }
+        #[derive(Debug, Serialize, Deserialize, Clone)]
+        struct Order {
+            uuid: String,
+            user: String,
+        }
+        impl Data for Order {
+            fn uuid(&self) -> String {
+                self.uuid.clone()
+            }
+        }
+        let (db, _db_dir) = setup();
+        db.create_collection("users").unwrap();
+        db.create_collection("orders").unwrap();
+        db.insert_data(
+            "users",
+            User {
+                uuid: "u1".to_string(),
+                name: "John".to_string(),
+            },
+        )
+        .unwrap();
+        for (uuid, user) in [("o1", "u1"), ("o2", "u2"), ("o3", "u1")] {
+            db.insert_data(
+                "orders",
+                Order {
+                    uuid: uuid.to_string(),
+                    user: user.to_string(),
+                },
+            )
+            .unwrap();
+        }
+        let r: Vec<(String, Option<String>)> = db
+            .join(
+                "orders",
+                "users",
+                |o: &Order| o.user.clone(),
+                |u: &User| u.uuid.clone(),
+                |o, u| (o.uuid, u.map(|u| u.name)),
+            )
+            .unwrap();
+        assert_eq!(
+            r,
+            vec![
+                ("o1".to_string(), Some("John".to_string())),
+                ("o2".to_string(), None),
+                ("o3".to_string(), Some("John".to_string())),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_configure_collection() {
+        #[derive(Debug, Serialize, Deserialize, Clone)]
+        struct TestData {
+            uuid: String,
+        }
+        impl Data for TestData {
+            fn uuid(&self) -> String {
+                self.uuid.clone()
+            }
+        }
+        let (mut db, _db_dir) = setup();
+        db.create_collection("capped").unwrap();
+        db.create_collection("plain").unwrap();
+        let config = CollectionConfig {
+            sorted: true,
+            max_records: Some(2),
+            ..Default::default()
+        };
+        db.configure_collection("capped", config).unwrap();
+        db.configure_collection("plain", CollectionConfig::default())
+            .unwrap();
+        for collection in ["capped", "plain"] {
+            for uuid in ["b", "a"] {
+                db.insert_data(
+                    collection,
+                    TestData {
+                        uuid: uuid.to_string(),
+                    },
+                )
+                .unwrap();
+            }
+        }
+        let data = TestData {
+            uuid: "c".to_string(),
+        };
+        assert!(db.insert_data("capped", data.clone()).is_err());
+        db.insert_data("plain", data).unwrap();
+        let capped: Vec<TestData> = db.read_collection("capped").unwrap();
+        let plain: Vec<TestData> = db.read_collection("plain").unwrap();
+        assert_eq!(
+            capped.iter().map(|i| i.uuid()).collect::<Vec<_>>(),
+            vec!["a", "b"]
+        );
+        assert_eq!(
+            plain.iter().map(|i| i.uuid()).collect::<Vec<_>>(),
+            vec!["b", "a", "c"]
+        );
+        // lowering the cap below the size still allows shrinking the collection
+        let config = CollectionConfig {
+            sorted: true,
+            max_records: Some(1),
+            ..Default::default()
+        };
+        db.configure_collection("capped", config).unwrap();
+        db.update_data(
+            "capped",
+            TestData {
+                uuid: "a".to_string(),
+            },
+        )
+        .unwrap();
+        db.delete_data::<TestData>("capped", "a").unwrap();
+        assert_eq!(db.ids("capped").unwrap(), vec!["b"]);
+        assert!(db
+            .configure_collection("missing", CollectionConfig::default())
+            .is_err());
+    }
+
+    #[cfg(feature = "pretty")]
+    #[test]
+    fn test_configure_collection_pretty() {
+        let (db, _db_dir) = setup();
+        db.create_collection("compact").unwrap();
+        db.create_collection("pretty").unwrap();
+        db.configure_collection(
+            "pretty",
+            CollectionConfig {
+                pretty: true,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        for collection in ["compact", "pretty"] {
+            db.write_collection(collection, vec![serde_json::json!({"uuid": "a"})])
+                .unwrap();
+        }
+        assert!(!fs::read_to_string(db.collection_path("compact"))
+            .unwrap()
+            .contains('\n'));
+        assert!(fs::read_to_string(db.collection_path("pretty"))
+            .unwrap()
+            .contains('\n'));
+    }
+
+    #[test]
+    fn test_register_canonicalizer() {
+        #[derive(Debug, Serialize, Deserialize, Clone)]
+        struct TestData {
+            email: String,
+        }
+        impl Data for TestData {
+            fn uuid(&self) -> String {
+                self.email.clone()
+            }
+        }
+        let (mut db, _db_dir) = setup();
+        db.create_collection("test").unwrap();
+        db.register_canonicalizer("test", |mut v: Value| {
+            let email = v["email"].as_str().unwrap().trim().to_lowercase();
+            v["email"] = Value::from(email);
+            v
+        });
+        let data = TestData {
+            email: " John@Example.COM".to_string(),
+        };
+        db.insert_data("test", data.clone()).unwrap();
+        assert_eq!(data.email, " John@Example.COM");
+        let r: Vec<TestData> = db.read_collection("test").unwrap();
+        assert_eq!(r[0].email, "john@example.com");
+        // uniqueness is checked against the canonical form
+        let duplicate = TestData {
+            email: "JOHN@example.com".to_string(),
+        };
+        assert!(db.insert_data("test", duplicate).is_err());
+        let r: TestData = db.query_data("test", "john@example.com").unwrap();
+        assert_eq!(r.email, "john@example.com");
+    }
+
+    #[test]
+    fn test_transfer_collection() {
+        #[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+        struct TestData {
+            uuid: String,
+            name: String,
+        }
+        impl Data for TestData {
+            fn uuid(&self) -> String {
+                self.uuid.clone()
+            }
+        }
+        let data = |uuid: &str, name: &str| TestData {
+            uuid: uuid.to_string(),
+            name: name.to_string(),
+        };
+        let (src, _src_dir) = setup();
+        let (dest, _dest_dir) = setup();
+        src.create_collection("test").unwrap();
+        src.insert_data("test", data("a", "new")).unwrap();
+        src.insert_data("test", data("b", "new")).unwrap();
+        dest.create_collection("copy").unwrap();
+        dest.insert_data("copy", data("a", "old")).unwrap();
+        assert!(src
+            .transfer_collection::<TestData>("test", &dest, "copy", MergePolicy::Fail)
+            .is_err());
+        let r: Vec<TestData> = dest.read_collection("copy").unwrap();
+        assert_eq!(r, vec![data("a", "old")]);
+        let written = src
+            .transfer_collection::<TestData>("test", &dest, "copy", MergePolicy::Skip)
+            .unwrap();
+        assert_eq!(written, 1);
+        let r: Vec<TestData> = dest.read_collection("copy").unwrap();
+        assert_eq!(r, vec![data("a", "old"), data("b", "new")]);
+        let written = src
+            .transfer_collection::<TestData>("test", &dest, "copy", MergePolicy::Overwrite)
+            .unwrap();
+        assert_eq!(written, 2);
+        let r: Vec<TestData> = dest.read_collection("copy").unwrap();
+        assert_eq!(r, vec![data("a", "new"), data("b", "new")]);
+        // missing destination collections are created
+        src.transfer_collection::<TestData>("test", &dest, "fresh", MergePolicy::Fail)
+            .unwrap();
+        let r: Vec<TestData> = dest.read_collection("fresh").unwrap();
+        assert_eq!(r.len(), 2);
+        // sorted destinations stay ordered by uuid
+        dest.create_collection("sorted").unwrap();
+        dest.configure_collection(
+            "sorted",
+            CollectionConfig {
+                sorted: true,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        dest.insert_data("sorted", data("c", "old")).unwrap();
+        src.transfer_collection::<TestData>("test", &dest, "sorted", MergePolicy::Fail)
+            .unwrap();
+        assert_eq!(dest.ids("sorted").unwrap(), vec!["a", "b", "c"]);
+    }
+
+    #[test]
+    fn test_find_or() {
+        #[derive(Debug, Serialize, Deserialize, Clone)]
+        struct TestData {
+            uuid: String,
+        }
+        impl Data for TestData {
+            fn uuid(&self) -> String {
+                self.uuid.clone()
+            }
+        }
+        let (db, _db_dir) = setup();
+        db.create_collection("test").unwrap();
+        db.insert_data(
+            "test",
+            TestData {
+                uuid: "41".to_string(),
+            },
+        )
+        .unwrap();
+        let r: TestData = db.find_or("test", "41", || DBError::new("unused")).unwrap();
+        assert_eq!(r.uuid, "41");
+        // messages can be built inside a function returning the error
+        fn find_user<'a>(
+            db: &'a Database,
+            collection: &str,
+            id: u32,
+        ) -> Result<TestData, DBError<'a>> {
+            db.find_or(collection, &id.to_string(), || {
+                DBError::new(format!("User {} not found", id))
+            })
+        }
+        assert_eq!(
+            find_user(&db, "test", 42).unwrap_err().0,
+            "User 42 not found"
+        );
+        // errors other than a missing record are returned as is
+        let r = find_user(&db, "missing", 42);
+        assert_eq!(r.unwrap_err().0, "Collection does not exist");
+    }
+
+    #[test]
+    fn test_field_histogram() {
+        let (db, _db_dir) = setup();
+        db.create_collection("test").unwrap();
+        db.write_collection(
+            "test",
+            vec![
+                serde_json::json!({"uuid": "1", "country": "fr"}),
+                serde_json::json!({"uuid": "2", "country": "de"}),
+                serde_json::json!({"uuid": "3", "country": "fr"}),
+                serde_json::json!({"uuid": "4", "country": null}),
+                serde_json::json!({"uuid": "5"}),
+            ],
+        )
+        .unwrap();
+        let r = db.field_histogram("test", "country").unwrap();
+        assert_eq!(r.len(), 3);
+        assert_eq!(r["fr"], 2);
+        assert_eq!(r["de"], 1);
+        assert_eq!(r["null"], 1);
+    }
+
+    type OpenHook = Box<dyn Fn(&Path) -> Option<Box<dyn Read + Send>> + Send + Sync>;
+    type WriteHook = Box<dyn Fn(&Path) -> io::Result<()> + Send + Sync>;
+
+    /// File system delegating to [StdFileSystem], except for the files served by `open` and the
+    /// writes failed by `write`
+    #[derive(Default)]
+    struct TestFileSystem {
+        open: Option<OpenHook>,
+        write: Option<WriteHook>,
+    }
+
+    impl FileSystem for TestFileSystem {
+        fn read_to_string(&self, path: &Path) -> io::Result<String> {
+            StdFileSystem.read_to_string(path)
+        }
+        fn open(&self, path: &Path) -> io::Result<Box<dyn Read + Send>> {
+            match self.open.as_ref().and_then(|open| open(path)) {
+                Some(reader) => Ok(reader),
+                None => StdFileSystem.open(path),
+            }
+        }
+        fn write(&self, path: &Path, contents: &[u8]) -> io::Result<()> {
+            if let Some(write) = &self.write {
+                write(path)?;
+            }
+            StdFileSystem.write(path, contents)
+        }
+        fn exists(&self, path: &Path) -> bool {
+            StdFileSystem.exists(path)
+        }
+        fn is_dir(&self, path: &Path) -> bool {
+            StdFileSystem.is_dir(path)
+        }
+        fn create_dir_all(&self, path: &Path) -> io::Result<()> {
+            StdFileSystem.create_dir_all(path)
+        }
+        fn remove_file(&self, path: &Path) -> io::Result<()> {
+            StdFileSystem.remove_file(path)
+        }
+        fn rename(&self, from: &Path, to: &Path) -> io::Result<()> {
+            StdFileSystem.rename(from, to)
+        }
+        fn read_dir(&self, path: &Path) -> io::Result<Vec<PathBuf>> {
+            StdFileSystem.read_dir(path)
+        }
+        fn read_subdirs(&self, path: &Path) -> io::Result<Vec<PathBuf>> {
+            StdFileSystem.read_subdirs(path)
+        }
+    }
+
+    /// Fails the first `failures` writes with `kind`, counting every write in `writes`
+    fn failing_writes(failures: usize, kind: io::ErrorKind, writes: Arc<AtomicUsize>) -> WriteHook {
+        Box::new(move |_| match writes.fetch_add(1, Ordering::SeqCst) {
+            n if n < failures => Err(io::Error::from(kind)),
+            _ => Ok(()),
+        })
+    }
+
+    fn setup_flaky(
+        failures: usize,
+        kind: io::ErrorKind,
+        retries: u32,
+    ) -> (Database, Arc<AtomicUsize>, TempDir) {
+        let writes = Arc::new(AtomicUsize::new(0));
+        let mut db = Database::new()
+            .with_file_system(TestFileSystem {
+                write: Some(failing_writes(failures, kind, writes.clone())),
+                ..Default::default()
+            })
+            .with_retry(retries, Duration::from_millis(1));
+        let db_dir = tempdir().unwrap();
+        db.connect(db_dir.path().to_path_buf()).unwrap();
+        (db, writes, db_dir)
+    }
+
+    #[test]
+    fn test_retry_transient_errors() {
+        let (db, writes, _db_dir) = setup_flaky(2, io::ErrorKind::Interrupted, 2);
+        db.create_collection("test").unwrap();
+        assert_eq!(writes.load(Ordering::SeqCst), 3);
+        assert!(db.path.join("test.json").exists());
+        // not enough retries
+        let (db, writes, _db_dir) = setup_flaky(2, io::ErrorKind::Interrupted, 1);
+        assert!(db.create_collection("test").is_err());
+        assert_eq!(writes.load(Ordering::SeqCst), 2);
+    }
+
+    #[test]
+    fn test_retry_skips_non_transient_errors() {
+        let (db, writes, _db_dir) = setup_flaky(1, io::ErrorKind::PermissionDenied, 2);
+        assert!(db.create_collection("test").is_err());
+        assert_eq!(writes.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn test_collection_iter() {
+        #[derive(Debug, Serialize, Deserialize, Clone)]
+        struct TestData {
+            uuid: String,
+            age: u32,
+        }
+        impl Data for TestData {
+            fn uuid(&self) -> String {
+                self.uuid.clone()
+            }
+        }
+        let (db, _db_dir) = setup();
+        db.create_collection("test").unwrap();
+        db.write_collection(
+            "test",
+            vec![
+                serde_json::json!({"uuid": "1", "age": 20}),
+                serde_json::json!({"uuid": "2", "age": "unknown"}),
+                serde_json::json!({"uuid": "3", "age": 22}),
+            ],
+        )
+        .unwrap();
+        let mut sum = 0;
+        let mut errors = 0;
+        for r in db.collection::<TestData>("test").iter().unwrap() {
+            match r {
+                Ok(r) => sum += r.age,
+                Err(_) => errors += 1,
+            }
+        }
+        assert_eq!(sum, 42);
+        assert_eq!(errors, 1);
+        let collection = db.collection::<TestData>("test");
+        assert_eq!((&collection).into_iter().count(), 3);
+        // opening a missing collection fails
+        assert!(db.collection::<TestData>("missing").iter().is_err());
+        let r: Vec<_> = (&db.collection::<TestData>("missing"))
+            .into_iter()
+            .collect();
+        assert_eq!(r.len(), 1);
+        assert!(r[0].is_err());
+    }
+
+    #[test]
+    fn test_ids_field_backed() {
+        #[derive(Debug, Serialize, Deserialize, Clone)]
+        struct TestData {
+            uuid: String,
+            name: String,
+        }
+        impl Data for TestData {
+            fn uuid(&self) -> String {
+                self.uuid.clone()
+            }
+        }
+        let (db, _db_dir) = setup();
+        db.create_collection("test").unwrap();
+        for uuid in ["a", "b"] {
+            db.insert_data(
+                "test",
+                TestData {
+                    uuid: uuid.to_string(),
+                    name: "test".to_string(),
+                },
+            )
+            .unwrap();
+        }
+        assert_eq!(db.ids("test").unwrap(), vec!["a", "b"]);
+        assert!(db.contains("test", "a").unwrap());
+        assert!(!db.contains("test", "c").unwrap());
+    }
+
+    #[test]
+    fn test_ids_derived() {
+        #[derive(Debug, Serialize, Deserialize, Clone)]
+        struct TestData {
+            name: String,
+        }
+        impl Data for TestData {
+            fn uuid(&self) -> String {
+                format!("user-{}", self.name)
+            }
+        }
+        let mut db = Database::new().with_id_policy(IdPolicy::Materialize);
+        let db_dir = tempdir().unwrap();
+        db.connect(db_dir.path().to_path_buf()).unwrap();
+        db.create_collection("test").unwrap();
+        db.insert_data(
+            "test",
+            TestData {
+                name: "john".to_string(),
+            },
+        )
+        .unwrap();
+        assert_eq!(db.ids("test").unwrap(), vec!["user-john"]);
+        let r: Vec<TestData> = db.list_data("test").unwrap();
+        assert_eq!(r[0].name, "john");
+        // by default derived ids are not stored
+        let mut db = Database::new();
+        let db_dir = tempdir().unwrap();
+        db.connect(db_dir.path().to_path_buf()).unwrap();
+        db.create_collection("test").unwrap();
+        db.insert_data(
+            "test",
+            TestData {
+                name: "john".to_string(),
+            },
+        )
+        .unwrap();
+        assert!(db.ids("test").is_err());
+    }
+
+    #[test]
+    fn test_ids_mismatching_field() {
+        #[derive(Debug, Serialize, Deserialize, Clone)]
+        struct TestData {
+            uuid: String,
+            name: String,
+        }
+        impl Data for TestData {
+            fn uuid(&self) -> String {
+                self.name.clone()
+            }
+        }
+        let mut db = Database::new().with_id_policy(IdPolicy::Materialize);
+        let db_dir = tempdir().unwrap();
+        db.connect(db_dir.path().to_path_buf()).unwrap();
+        db.create_collection("test").unwrap();
+        let data = TestData {
+            uuid: "a".to_string(),
+            name: "b".to_string(),
+        };
+        assert!(db.insert_data("test", data).is_err());
+    }
+
+    #[test]
+    fn test_default_id_policy_keeps_stored_format() {
+        #[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+        #[serde(deny_unknown_fields)]
+        struct Strict {
+            name: String,
+        }
+        impl Data for Strict {
+            fn uuid(&self) -> String {
+                self.name.clone()
+            }
+        }
+        let (db, _db_dir) = setup();
+        db.create_collection("test").unwrap();
+        let data = Strict {
+            name: "john".to_string(),
+        };
+        db.insert_data("test", data.clone()).unwrap();
+        let r: Vec<Strict> = db.list_data("test").unwrap();
+        assert_eq!(r, vec![data]);
+    }
+
+    #[cfg(feature = "indexes")]
+    #[test]
+    fn test_query_by_index_range() {
+        #[derive(Debug, Serialize, Deserialize, Clone)]
+        struct TestData {
+            uuid: String,
+            age: u32,
+        }
+        impl Data for TestData {
+            fn uuid(&self) -> String {
+                self.uuid.clone()
+            }
+        }
+        let (mut db, _db_dir) = setup();
+        db.create_collection("test").unwrap();
+        let data = |uuid: &str, age: u32| TestData {
+            uuid: uuid.to_string(),
+            age,
+        };
+        db.insert_data("test", data("a", 30)).unwrap();
+        db.insert_data("test", data("b", 18)).unwrap();
+        db.create_index("test", "by_age", "age").unwrap();
+        assert!(db.create_index("test", "by_age", "age").is_err());
+        // records written after the index is created are indexed too
+        db.insert_data("test", data("c", 20)).unwrap();
+        db.insert_data("test", data("d", 25)).unwrap();
+        db.insert_data("test", data("e", 40)).unwrap();
+        let r: Vec<TestData> = db.query_by_index_range("test", "by_age", 20..=30).unwrap();
+        assert_eq!(
+            r.iter().map(|i| i.uuid()).collect::<Vec<_>>(),
+            vec!["c", "d", "a"]
+        );
+        let r: Vec<TestData> = db.query_by_index_range("test", "by_age", ..20).unwrap();
+        assert_eq!(r.iter().map(|i| i.uuid()).collect::<Vec<_>>(), vec!["b"]);
+        db.delete_data::<TestData>("test", "d").unwrap();
+        let r: Vec<TestData> = db.query_by_index_range("test", "by_age", 20..=30).unwrap();
+        assert_eq!(
+            r.iter().map(|i| i.uuid()).collect::<Vec<_>>(),
+            vec!["c", "a"]
+        );
+        assert!(db
+            .query_by_index_range::<TestData, u32>("test", "missing", ..)
+            .is_err());
+        // records are indexed by their stored id
+        #[derive(Debug, Serialize, Deserialize, Clone)]
+        struct Named {
+            name: String,
+            age: u32,
+        }
+        impl Data for Named {
+            fn uuid(&self) -> String {
+                self.name.clone()
+            }
+        }
+        db.create_collection("named").unwrap();
+        let named = Named {
+            name: "f".to_string(),
+            age: 20,
+        };
+        db.insert_data("named", named).unwrap();
+        assert!(db.create_index("named", "by_age", "age").is_err());
+        db.configure_collection(
+            "named",
+            CollectionConfig {
+                id_key: Some("name".to_string()),
+                ..CollectionConfig::default()
+            },
+        )
+        .unwrap();
+        db.create_index("named", "by_age", "age").unwrap();
+        let r: Vec<Named> = db.query_by_index_range("named", "by_age", 20..).unwrap();
+        assert_eq!(r[0].name, "f");
+    }
+
+    #[test]
+    fn test_path_translator() {
+        #[derive(Debug, Serialize, Deserialize, Clone)]
+        struct TestData {
+            uuid: String,
+            name: String,
+        }
+        impl Data for TestData {
+            fn uuid(&self) -> String {
+                self.uuid.clone()
+            }
+        }
+        let mut db = Database::new().with_path_translator(|file| Path::new("data").join(file));
+        let db_dir = tempdir().unwrap();
+        db.connect(db_dir.path().to_path_buf()).unwrap();
+        assert!(db.list_collections().unwrap().is_empty());
+        db.create_collection("test").unwrap();
+        assert!(db_dir.path().join("data/test.json").exists());
+        assert!(!db_dir.path().join("test.json").exists());
+        let data = TestData {
+            uuid: "a".to_string(),
+            name: "test".to_string(),
+        };
+        db.insert_data("test", data.clone()).unwrap();
+        db.update_data(
+            "test",
+            TestData {
+                name: "test2".to_string(),
+                ..data
+            },
+        )
+        .unwrap();
+        let r: TestData = db.query_data("test", "a").unwrap();
+        assert_eq!(r.name, "test2");
+        db.configure_collection("test", CollectionConfig::default())
+            .unwrap();
+        assert!(db_dir.path().join("data/test.meta").exists());
+        assert_eq!(db.list_collections().unwrap(), vec!["test"]);
+        db.rename_collection("test", "test2").unwrap();
+        assert!(db_dir.path().join("data/test2.json").exists());
+        assert!(db_dir.path().join("data/test2.meta").exists());
+        db.delete_data::<TestData>("test2", "a").unwrap();
+        let r: Vec<TestData> = db.list_data("test2").unwrap();
+        assert!(r.is_empty());
+        db.delete_collection("test2").unwrap();
+        assert!(db.list_collections().unwrap().is_empty());
+        assert!(!db_dir.path().join("data/test2.meta").exists());
+    }
+
+    #[test]
+    fn test_path_translator_directory_per_collection() {
+        let mut db = Database::new().with_path_translator(|file| {
+            let name = file.split('.').next().unwrap_or_default();
+            Path::new(name).join(file)
+        });
+        let db_dir = tempdir().unwrap();
+        db.connect(db_dir.path().to_path_buf()).unwrap();
+        db.create_collection("users").unwrap();
+        assert!(db_dir.path().join("users/users.json").exists());
+        db.configure_collection("users", CollectionConfig::default())
+            .unwrap();
+        db.rename_collection("users", "admins").unwrap();
+        assert!(db_dir.path().join("admins/admins.json").exists());
+        assert!(db_dir.path().join("admins/admins.meta").exists());
+        assert_eq!(db.count("admins").unwrap(), 0);
+    }
+
+    #[test]
+    fn test_total_records() {
+        let (db, _db_dir) = setup();
+        db.create_collection("a").unwrap();
+        db.create_collection("b").unwrap();
+        db.create_collection("c").unwrap();
+        db.write_collection(
+            "a",
+            vec![
+                serde_json::json!({"uuid": "1"}),
+                serde_json::json!({"uuid": "2"}),
+            ],
+        )
+        .unwrap();
+        db.write_collection("b", vec![serde_json::json!({"uuid": "1"})])
+            .unwrap();
+        assert_eq!(db.count_collections().unwrap(), 3);
+        assert_eq!(db.total_records().unwrap(), 3);
+        // corrupted collections are skipped and reported
+        fs::write(db.collection_path("c"), "[{\"uuid\": ").unwrap();
+        assert_eq!(db.total_records().unwrap(), 3);
+        let summary = db.record_summary().unwrap();
+        assert_eq!(summary.total, 3);
+        assert_eq!(summary.unreadable, vec!["c"]);
+        assert!(db.count("c").is_err());
+        assert!(db.read_collection::<Value>("c").is_err());
+        // a malformed record inside a well formed array
+        fs::write(db.collection_path("c"), "[{\"uuid\": }]").unwrap();
+        assert!(db.count("c").is_err());
+        assert_eq!(db.record_summary().unwrap().unreadable, vec!["c"]);
+    }
+
+    #[test]
+    fn test_compact_merge_duplicates() {
+        let (db, _db_dir) = setup();
+        db.create_collection("test").unwrap();
+        let records = vec![
+            serde_json::json!({"uuid": "1", "name": "john", "age": 20}),
+            serde_json::json!({"uuid": "2", "name": "jane", "age": 20}),
+            serde_json::json!({"age": 20, "uuid": "3", "name": "john"}),
+        ];
+        db.write_collection("test", records).unwrap();
+        // duplicates are only merged when asked for
+        let report = db.compact("test", CompactOptions::default()).unwrap();
+        assert_eq!(report.records, 3);
+        assert!(report.merged.is_empty());
+        let report = db
+            .compact(
+                "test",
+                CompactOptions {
+                    merge_duplicates: true,
+                },
+            )
+            .unwrap();
+        assert_eq!(report.records, 2);
+        assert_eq!(report.merged, vec![("1".to_string(), "3".to_string())]);
+        assert_eq!(report.merged_positions, vec![(0, 2)]);
+        assert_eq!(db.ids("test").unwrap(), vec!["1", "2"]);
+        // records without a stored id are reported by position
+        db.create_collection("derived").unwrap();
+        db.clear_dirty();
+        let records = vec![
+            serde_json::json!({"name": "john"}),
+            serde_json::json!({"name": "jane"}),
+            serde_json::json!({"name": "john"}),
+        ];
+        db.write_collection("derived", records).unwrap();
+        let report = db
+            .compact(
+                "derived",
+                CompactOptions {
+                    merge_duplicates: true,
+                },
+            )
+            .unwrap();
+        assert_eq!(report.records, 2);
+        assert!(report.merged.is_empty());
+        assert_eq!(report.merged_positions, vec![(0, 2)]);
+        assert!(db.dirty().is_empty());
+    }
+
+    #[test]
+    fn test_open_collection_typed() {
+        #[derive(Debug, Serialize, Deserialize, Clone)]
+        struct TestData {
+            uuid: String,
+            name: String,
+        }
+        impl Data for TestData {
+            fn uuid(&self) -> String {
+                self.uuid.clone()
+            }
+        }
+        let (db, _db_dir) = setup();
+        db.create_collection("test").unwrap();
+        let users = db.open_collection_typed::<TestData>("test").unwrap();
+        let data = TestData {
+            uuid: "a".to_string(),
+            name: "test".to_string(),
+        };
+        users.insert(data.clone()).unwrap();
+        assert!(users.insert(data.clone()).is_err());
+        users
+            .update(TestData {
+                name: "test2".to_string(),
+                ..data
+            })
+            .unwrap();
+        assert_eq!(users.query("a").unwrap().name, "test2");
+        assert_eq!(users.list().unwrap().len(), 1);
+        users.delete("a").unwrap();
+        assert!(users.list().unwrap().is_empty());
+        // deleted out from under the handle
+        db.delete_collection("test").unwrap();
+        assert_eq!(users.list().unwrap_err().0, "Collection was deleted");
+        assert_eq!(
+            users
+                .insert(TestData {
+                    uuid: "b".to_string(),
+                    name: "test".to_string(),
+                })
+                .unwrap_err()
+                .0,
+            "Collection was deleted"
+        );
+        assert!(!db.path.join("test.json").exists());
+    }
+
+    #[test]
+    fn test_open_collection_typed_missing() {
+        #[derive(Debug, Serialize, Deserialize, Clone)]
+        struct TestData {
+            uuid: String,
+        }
+        impl Data for TestData {
+            fn uuid(&self) -> String {
+                self.uuid.clone()
+            }
+        }
+        let (db, _db_dir) = setup();
+        let r = db.open_collection_typed::<TestData>("missing");
+        assert_eq!(r.err().unwrap().0, "Collection does not exist");
+    }
+
+    #[test]
+    fn test_collection_cache_eviction() {
+        #[derive(Debug, Serialize, Deserialize, Clone)]
+        struct TestData {
+            uuid: String,
         }
-        // create collection
-        let r = fs::write(collection_path, "[]");
-        if r.is_err() {
-            print!("{}", r.err().unwrap());
-            return Result::Err(DBError("Could not create collection"));
+        impl Data for TestData {
+            fn uuid(&self) -> String {
+                self.uuid.clone()
+            }
         }
-        Result::Ok(())
+        let on_disk = |db: &Database, collection: &str| -> Vec<Value> {
+            serde_json::from_str(&fs::read_to_string(db.collection_path(collection)).unwrap())
+                .unwrap()
+        };
+        let mut db = Database::new().with_collection_cache(2);
+        let db_dir = tempdir().unwrap();
+        db.connect(db_dir.path().to_path_buf()).unwrap();
+        for collection in ["a", "b", "c"] {
+            db.create_collection(collection).unwrap();
+        }
+        let data = TestData {
+            uuid: "1".to_string(),
+        };
+        let a = db.open_collection_typed::<TestData>("a").unwrap();
+        let b = db.open_collection_typed::<TestData>("b").unwrap();
+        let c = db.open_collection_typed::<TestData>("c").unwrap();
+        a.insert(data.clone()).unwrap();
+        b.insert(data.clone()).unwrap();
+        // writes are buffered
+        assert!(on_disk(&db, "a").is_empty());
+        assert_eq!(a.list().unwrap().len(), 1);
+        // b is now the least recently used collection and gets evicted and flushed
+        c.insert(data.clone()).unwrap();
+        assert_eq!(on_disk(&db, "b").len(), 1);
+        assert!(on_disk(&db, "a").is_empty());
+        assert!(on_disk(&db, "c").is_empty());
+        assert_eq!(b.list().unwrap().len(), 1);
+        db.flush().unwrap();
+        assert_eq!(on_disk(&db, "a").len(), 1);
+        assert_eq!(on_disk(&db, "c").len(), 1);
     }
 
-    /// Lists collection in the database
-    fn list_collections(&self) -> Result<Vec<String>, DBError> {
-        self.path
-            .read_dir()
-            .map(|r| {
-                r.filter_map(|r| r.ok())
-                    .filter(|r| r.path().is_file())
-                    .filter(|r| r.path().extension().unwrap_or_default() == "json")
-                    .map(|r| r.path().file_stem().unwrap().to_str().unwrap().to_string())
-                    .collect()
+    #[test]
+    fn test_collection_cache_concurrent_eviction() {
+        #[derive(Debug, Serialize, Deserialize, Clone)]
+        struct TestData {
+            uuid: String,
+        }
+        impl Data for TestData {
+            fn uuid(&self) -> String {
+                self.uuid.clone()
+            }
+        }
+        let data = |uuid: &str| TestData {
+            uuid: uuid.to_string(),
+        };
+        // once armed, the write of x.json signals and then stalls
+        let armed = Arc::new(AtomicBool::new(false));
+        let (stalled, on_stall) = mpsc::channel();
+        let write: WriteHook = {
+            let armed = armed.clone();
+            Box::new(move |path| {
+                if path.ends_with("x.json") && armed.swap(false, Ordering::SeqCst) {
+                    stalled.send(()).unwrap();
+                    std::thread::sleep(Duration::from_millis(100));
+                }
+                Ok(())
+            })
+        };
+        let mut db = Database::new()
+            .with_file_system(TestFileSystem {
+                write: Some(write),
+                ..Default::default()
             })
-            .map_err(|_| DBError("Could not list collections"))
+            .with_collection_cache(1);
+        let db_dir = tempdir().unwrap();
+        db.connect(db_dir.path().to_path_buf()).unwrap();
+        db.create_collection("x").unwrap();
+        db.create_collection("y").unwrap();
+        db.insert_data("x", data("1")).unwrap();
+        armed.store(true, Ordering::SeqCst);
+        std::thread::scope(|scope| {
+            let db = &db;
+            // evicts the buffered x
+            scope.spawn(move || db.insert_data("y", data("a")).unwrap());
+            // writes x while its eviction is being flushed
+            on_stall.recv().unwrap();
+            db.insert_data("x", data("2")).unwrap();
+        });
+        db.flush().unwrap();
+        assert_eq!(db.ids("x").unwrap(), vec!["1", "2"]);
+        assert_eq!(db.ids("y").unwrap(), vec!["a"]);
     }
 
-    /// Deletes a collection from the database
-    fn delete_collection(&self, name: &str) -> Result<(), DBError> {
-        let mut name = name.to_lowercase();
-        name.push_str(".json");
-        let collection_path = self.path.join(name);
-        if !collection_path.exists() {
-            return Result::Err(DBError("Collection does not exist"));
-        }
-        let r = fs::remove_file(collection_path);
-        if r.is_err() {
-            return Result::Err(DBError("Could not delete collection"));
+    #[test]
+    fn test_collection_cache_flush_on_drop() {
+        let db_dir = tempdir().unwrap();
+        {
+            let mut db = Database::new().with_collection_cache(1);
+            db.connect(db_dir.path().to_path_buf()).unwrap();
+            db.create_collection("test").unwrap();
+            db.write_collection("test", vec![serde_json::json!({"uuid": "1"})])
+                .unwrap();
+            // streaming reads see buffered writes
+            assert_eq!(db.count("test").unwrap(), 1);
+            db.write_collection("test", vec![serde_json::json!({"uuid": "2"})])
+                .unwrap();
         }
-        Result::Ok(())
+        let mut db = Database::new();
+        db.connect(db_dir.path().to_path_buf()).unwrap();
+        assert_eq!(db.ids("test").unwrap(), vec!["2"]);
     }
 
-    /// Inserts data into a collection in the database
-    fn insert_data<T: Data>(&self, collection: &str, data: T) -> Result<(), DBError> {
-        let mut c: Vec<T> = self.read_collection(collection)?;
-        for i in &c {
-            if i.uuid() == data.uuid() {
-                return Result::Err(DBError("Data already exists"));
-            }
+    #[test]
+    fn test_collection_cache_keeps_failed_flushes() {
+        let db_dir = tempdir().unwrap();
+        for collection in ["a", "b"] {
+            fs::write(db_dir.path().join(format!("{}.json", collection)), "[]").unwrap();
         }
-        c.push(data);
-        self.write_collection(collection, c)?;
-        Result::Ok(())
+        let mut db = Database::new()
+            .with_file_system(TestFileSystem {
+                write: Some(failing_writes(
+                    1,
+                    io::ErrorKind::PermissionDenied,
+                    Arc::new(AtomicUsize::new(0)),
+                )),
+                ..Default::default()
+            })
+            .with_collection_cache(2);
+        db.connect(db_dir.path().to_path_buf()).unwrap();
+        db.write_collection("a", vec![serde_json::json!({"uuid": "1"})])
+            .unwrap();
+        db.write_collection("b", vec![serde_json::json!({"uuid": "2"})])
+            .unwrap();
+        // the write of a fails, b is still written
+        assert!(db.flush().is_err());
+        assert_eq!(
+            fs::read_to_string(db_dir.path().join("a.json")).unwrap(),
+            "[]"
+        );
+        assert_ne!(
+            fs::read_to_string(db_dir.path().join("b.json")).unwrap(),
+            "[]"
+        );
+        // a stays buffered and is written by the next flush
+        db.flush().unwrap();
+        assert_eq!(db.ids("a").unwrap(), vec!["1"]);
+        assert_eq!(db.ids("b").unwrap(), vec!["2"]);
     }
 
-    /// Queries data from a collection in the database
-    fn query_data<T: Data>(&mut self, collection: &str, uuid: &str) -> Result<T, DBError> {
-        let c: Vec<T> = self.read_collection(collection)?;
-        for i in &c {
-            if i.uuid() == uuid {
-                return Result::Ok(i.clone());
+    #[test]
+    fn test_find_indexed() {
+        #[derive(Debug, Serialize, Deserialize, Clone)]
+        struct TestData {
+            uuid: String,
+        }
+        impl Data for TestData {
+            fn uuid(&self) -> String {
+                self.uuid.clone()
             }
         }
-        Result::Err(DBError("Data not found"))
+        let (db, _db_dir) = setup();
+        db.create_collection("test").unwrap();
+        for uuid in ["a", "b", "c"] {
+            db.insert_data(
+                "test",
+                TestData {
+                    uuid: uuid.to_string(),
+                },
+            )
+            .unwrap();
+        }
+        let (i, r) = db.find_indexed::<TestData>("test", "b").unwrap().unwrap();
+        assert_eq!(i, 1);
+        assert_eq!(r.uuid, "b");
+        let (i, _) = db.find_indexed::<TestData>("test", "c").unwrap().unwrap();
+        assert_eq!(i, 2);
+        assert!(db.find_indexed::<TestData>("test", "d").unwrap().is_none());
     }
 
-    /// Updates data in a collection in the database
-    fn update_data<T: Data>(&mut self, collection: &str, data: T) -> Result<(), DBError> {
-        let mut c: Vec<T> = self.read_collection(collection)?;
-        for i in 0..c.len() {
-            if c[i].uuid() == data.uuid() {
-                c[i] = data;
-                self.write_collection(collection, c)?;
-                return Result::Ok(());
-            }
+    /// Backend keeping a collection in memory
+    #[derive(Clone, Default)]
+    struct MemoryBackend(Arc<Mutex<Vec<Value>>>);
+
+    impl CollectionBackend for MemoryBackend {
+        fn read_all(&self) -> Result<Vec<Value>, DBError<'static>> {
+            Ok(self.0.lock().unwrap().clone())
+        }
+        fn write_all(&self, records: Vec<Value>) -> Result<(), DBError<'static>> {
+            *self.0.lock().unwrap() = records;
+            Ok(())
         }
-        Result::Err(DBError("Data not found"))
     }
 
-    /// Deletes data from a collection in the database
-    fn delete_data<T: Data>(&mut self, collection: &str, uuid: &str) -> Result<(), DBError> {
-        let mut c: Vec<T> = self.read_collection(collection)?;
-        for i in 0..c.len() {
-            if c[i].uuid() == uuid {
-                c.remove(i);
-                self.write_collection(collection, c)?;
-                return Result::Ok(());
+    #[test]
+    fn test_mount_collection() {
+        #[derive(Debug, Serialize, Deserialize, Clone)]
+        struct TestData {
+            uuid: String,
+            name: String,
+        }
+        impl Data for TestData {
+            fn uuid(&self) -> String {
+                self.uuid.clone()
             }
         }
-        Result::Err(DBError("Data not found"))
-    }
-
-    /// Lists data from a collection in the database
-    fn list_data<T: Data>(&self, collection: &str) -> Result<Vec<T>, DBError> {
-        self.read_collection(collection)
+        let (mut db, _db_dir) = setup();
+        let backend = MemoryBackend::default();
+        db.mount_collection("test", backend.clone()).unwrap();
+        assert!(db.mount_collection("test", backend.clone()).is_err());
+        assert!(db.create_collection("test").is_err());
+        assert!(!db.path.join("test.json").exists());
+        assert_eq!(db.list_collections().unwrap(), vec!["test"]);
+        let data = |uuid: &str, name: &str| TestData {
+            uuid: uuid.to_string(),
+            name: name.to_string(),
+        };
+        // insert
+        db.insert_data("test", data("a", "test")).unwrap();
+        db.insert_data("test", data("b", "test")).unwrap();
+        assert!(db.insert_data("test", data("a", "test")).is_err());
+        assert_eq!(backend.0.lock().unwrap().len(), 2);
+        // query
+        let r: TestData = db.query_data("test", "a").unwrap();
+        assert_eq!(r.name, "test");
+        // update
+        db.update_data("test", data("a", "test2")).unwrap();
+        let r: TestData = db.query_data("test", "a").unwrap();
+        assert_eq!(r.name, "test2");
+        // delete
+        db.delete_data::<TestData>("test", "b").unwrap();
+        let r: Vec<TestData> = db.list_data("test").unwrap();
+        assert_eq!(r.len(), 1);
+        // typeless and streaming reads
+        assert_eq!(db.ids("test").unwrap(), vec!["a"]);
+        assert_eq!(db.count("test").unwrap(), 1);
+        // rename and delete the mount
+        db.rename_collection("test", "test2").unwrap();
+        assert_eq!(db.list_collections().unwrap(), vec!["test2"]);
+        let r: Vec<TestData> = db.list_data("test2").unwrap();
+        assert_eq!(r.len(), 1);
+        db.delete_collection("test2").unwrap();
+        assert!(db.list_collections().unwrap().is_empty());
+        assert!(db.list_data::<TestData>("test2").is_err());
+        // file backend outside of the database folder
+        let other_dir = tempdir().unwrap();
+        let path = other_dir.path().join("files.json");
+        let backend = FileBackend::new(Arc::new(StdFileSystem), path.clone());
+        backend.write_all(Vec::new()).unwrap();
+        db.mount_collection("files", backend).unwrap();
+        db.insert_data("files", data("a", "test")).unwrap();
+        assert!(fs::read_to_string(path).unwrap().contains("\"a\""));
+        assert!(!db.path.join("files.json").exists());
     }
 
-    /// Updates the name of a collection in the database
-    fn rename_collection(&self, name: &str, new_name: &str) -> Result<(), DBError> {
-        let mut name = name.to_lowercase();
-        name.push_str(".json");
-        let mut new_name = new_name.to_lowercase();
-        new_name.push_str(".json");
-        let collection_path = self.path.join(name);
-        let new_collection_path = self.path.join(new_name);
-        if !collection_path.exists() {
-            return Result::Err(DBError("Collection does not exist"));
-        }
-        if new_collection_path.exists() {
-            return Result::Err(DBError("Collection already exists"));
-        }
-        let r = fs::rename(collection_path, new_collection_path);
-        if r.is_err() {
-            return Result::Err(DBError("Could not rename collection"));
+    #[test]
+    fn test_upsert_many_with() {
+        #[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+        struct TestData {
+            uuid: String,
+            tags: Vec<String>,
         }
-        Result::Ok(())
+        impl Data for TestData {
+            fn uuid(&self) -> String {
+                self.uuid.clone()
+            }
+        }
+        let data = |uuid: &str, tags: &[&str]| TestData {
+            uuid: uuid.to_string(),
+            tags: tags.iter().map(|t| t.to_string()).collect(),
+        };
+        let merge = |old: &TestData, new: &TestData| {
+            let mut merged = old.clone();
+            for tag in &new.tags {
+                if !merged.tags.contains(tag) {
+                    merged.tags.push(tag.clone());
+                }
+            }
+            merged
+        };
+        let (db, _db_dir) = setup();
+        db.create_collection("test").unwrap();
+        db.insert_data("test", data("a", &["x"])).unwrap();
+        db.insert_data("test", data("b", &["y"])).unwrap();
+        let summary = db
+            .upsert_many_with(
+                "test",
+                vec![
+                    data("a", &["x", "z"]),
+                    data("c", &["w"]),
+                    data("b", &["v"]),
+                    // duplicate within the batch
+                    data("c", &["u"]),
+                ],
+                merge,
+            )
+            .unwrap();
+        assert_eq!(
+            summary,
+            UpsertSummary {
+                inserted: 1,
+                resolved: 3,
+            }
+        );
+        let r: Vec<TestData> = db.list_data("test").unwrap();
+        assert_eq!(
+            r,
+            vec![
+                data("a", &["x", "z"]),
+                data("b", &["y", "v"]),
+                data("c", &["w", "u"]),
+            ]
+        );
+        // plain upserts keep the latest record
+        let summary = db
+            .upsert_many("test", vec![data("a", &["q"]), data("d", &[])])
+            .unwrap();
+        assert_eq!(summary.inserted, 1);
+        assert_eq!(summary.resolved, 1);
+        let r: TestData = db.find_or("test", "a", || DBError::new("")).unwrap();
+        assert_eq!(r, data("a", &["q"]));
+        // sorted collections stay ordered by uuid
+        db.create_collection("sorted").unwrap();
+        db.configure_collection(
+            "sorted",
+            CollectionConfig {
+                sorted: true,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        db.insert_data("sorted", data("b", &[])).unwrap();
+        db.upsert_many("sorted", vec![data("c", &[]), data("a", &[])])
+            .unwrap();
+        assert_eq!(db.ids("sorted").unwrap(), vec!["a", "b", "c"]);
     }
-}
-
-#[cfg(test)]
-mod test {
-    use super::*;
-    use serde::Deserialize;
-    use tempfile::{tempdir, TempDir};
 
-    fn setup() -> (Database, TempDir) {
-        let mut db = Database::new();
+    #[test]
+    fn test_validate_on_connect() {
         let db_dir = tempdir().unwrap();
+        fs::write(db_dir.path().join("good.json"), r#"[{"uuid":"a"}]"#).unwrap();
+        fs::write(db_dir.path().join("bad.json"), r#"[{"uuid":"#).unwrap();
+        // lazy connect succeeds, the corrupt collection fails when used
+        let mut db = Database::new();
         db.connect(db_dir.path().to_path_buf()).unwrap();
-        (db, db_dir)
+        assert!(db.connect_report().is_none());
+        assert!(db.read_collection::<Value>("bad").is_err());
+        // eager connect reports the corrupt collection and stays on the previous directory
+        let (mut db, other_dir) = setup();
+        db = db.with_validate_on_connect(true);
+        assert!(db.connect(db_dir.path().to_path_buf()).is_err());
+        let report = db.connect_report().unwrap();
+        assert_eq!(report.total, 1);
+        assert_eq!(report.unreadable, vec!["bad".to_string()]);
+        assert_eq!(db.path, other_dir.path());
+        fs::remove_file(db_dir.path().join("bad.json")).unwrap();
+        db.connect(db_dir.path().to_path_buf()).unwrap();
+        assert!(db.connect_report().unwrap().unreadable.is_empty());
     }
 
     #[test]
-    fn test_connect() {
-        let (_db, db_dir) = setup();
-        assert!(db_dir.path().exists());
-        assert!(db_dir.path().is_dir());
+    fn test_changed_since() {
+        #[derive(Debug, Serialize, Deserialize, Clone)]
+        struct TestData {
+            uuid: String,
+        }
+        impl Data for TestData {
+            fn uuid(&self) -> String {
+                self.uuid.clone()
+            }
+        }
+        let (db, _db_dir) = setup();
+        db.create_collection("test").unwrap();
+        let id = db.snapshot().unwrap();
+        assert!(!db.changed_since(&id).unwrap());
+        assert_eq!(db.snapshot().unwrap(), id);
+        db.insert_data(
+            "test",
+            TestData {
+                uuid: "a".to_string(),
+            },
+        )
+        .unwrap();
+        assert!(db.changed_since(&id).unwrap());
+        let id = db.snapshot().unwrap();
+        assert!(!db.changed_since(&id).unwrap());
+        db.configure_collection(
+            "test",
+            CollectionConfig {
+                sorted: true,
+                ..CollectionConfig::default()
+            },
+        )
+        .unwrap();
+        assert!(db.changed_since(&id).unwrap());
+        let id = db.snapshot().unwrap();
+        db.create_collection("other").unwrap();
+        assert!(db.changed_since(&id).unwrap());
+        let id = db.snapshot().unwrap();
+        db.delete_collection("other").unwrap();
+        assert!(db.changed_since(&id).unwrap());
+        // byte collections are part of the content
+        let id = db.snapshot().unwrap();
+        db.insert_bytes("files", "a", b"content").unwrap();
+        assert!(db.changed_since(&id).unwrap());
+        let id = db.snapshot().unwrap();
+        db.insert_bytes("files", "b", b"").unwrap();
+        assert!(db.changed_since(&id).unwrap());
+        assert_eq!(db.snapshot().unwrap(), db.snapshot().unwrap());
     }
 
     #[test]
-    fn test_create_collection() {
+    fn test_seed_from_dir() {
         let (db, _db_dir) = setup();
-        db.create_collection("test").unwrap();
-        assert!(db.path.join("test.json").exists());
-        assert!(db.path.join("test.json").is_file());
+        let fixtures = tempdir().unwrap();
+        fs::write(
+            fixtures.path().join("users.json"),
+            r#"[{"uuid":"a","name":"Ann"},{"uuid":"b","name":"Bob"}]"#,
+        )
+        .unwrap();
+        fs::write(fixtures.path().join("posts.json"), r#"[{"uuid":1}]"#).unwrap();
+        fs::write(fixtures.path().join("notes.txt"), "not a collection").unwrap();
+        let report = db
+            .seed_from_dir(fixtures.path(), MergePolicy::Fail)
+            .unwrap();
+        assert_eq!(
+            report.collections,
+            vec![("posts".to_string(), 1), ("users".to_string(), 2)]
+        );
+        let mut collections = db.list_collections().unwrap();
+        collections.sort();
+        assert_eq!(collections, vec!["posts", "users"]);
+        assert_eq!(db.count("users").unwrap(), 2);
+        assert_eq!(db.count("posts").unwrap(), 1);
+        // seeding again conflicts with the existing records
+        assert!(db
+            .seed_from_dir(fixtures.path(), MergePolicy::Fail)
+            .is_err());
+        let report = db
+            .seed_from_dir(fixtures.path(), MergePolicy::Skip)
+            .unwrap();
+        assert_eq!(report.skipped, 3);
+        assert_eq!(db.count("users").unwrap(), 2);
+        // invalid files are rejected before anything is written
+        fs::write(fixtures.path().join("users.json"), r#"[{"uuid":"c"}]"#).unwrap();
+        fs::write(fixtures.path().join("broken.json"), r#"{"uuid":"d"}"#).unwrap();
+        assert!(db
+            .seed_from_dir(fixtures.path(), MergePolicy::Overwrite)
+            .is_err());
+        assert_eq!(db.count("users").unwrap(), 2);
+        assert!(!db.collection_exists("broken"));
+        // seeding an existing sorted collection keeps it ordered by id
+        fs::remove_file(fixtures.path().join("broken.json")).unwrap();
+        db.configure_collection(
+            "users",
+            CollectionConfig {
+                sorted: true,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        fs::write(fixtures.path().join("users.json"), r#"[{"uuid":"0"}]"#).unwrap();
+        db.seed_from_dir(fixtures.path(), MergePolicy::Skip)
+            .unwrap();
+        assert_eq!(db.ids("users").unwrap(), vec!["0", "a", "b"]);
+        // a collection over its cap fails the whole seed before anything is written
+        db.configure_collection(
+            "users",
+            CollectionConfig {
+                max_records: Some(3),
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        fs::write(fixtures.path().join("users.json"), r#"[{"uuid":"c"}]"#).unwrap();
+        fs::write(fixtures.path().join("accounts.json"), r#"[{"uuid":"x"}]"#).unwrap();
+        assert!(db
+            .seed_from_dir(fixtures.path(), MergePolicy::Skip)
+            .is_err());
+        assert!(!db.collection_exists("accounts"));
+        assert_eq!(db.count("users").unwrap(), 3);
     }
 
     #[test]
-    fn test_insert_data() {
+    fn test_lock_granularity() {
         #[derive(Debug, Serialize, Deserialize, Clone)]
         struct TestData {
             uuid: String,
-            name: String,
         }
         impl Data for TestData {
             fn uuid(&self) -> String {
                 self.uuid.clone()
             }
         }
-        let (db, _db_dir) = setup();
-        db.create_collection("test").unwrap();
-        let data = TestData {
-            uuid: "test".to_string(),
-            name: "test".to_string(),
-        };
-        db.insert_data("test", data.clone()).unwrap();
-        let r: Vec<TestData> = db.read_collection("test").unwrap();
-        assert_eq!(r[0].uuid, data.uuid);
+        for granularity in [LockGranularity::PerCollection, LockGranularity::Global] {
+            let db_dir = tempdir().unwrap();
+            let mut db = Database::new().with_lock_granularity(granularity);
+            db.connect(db_dir.path().to_path_buf()).unwrap();
+            db.create_collection("a").unwrap();
+            db.create_collection("b").unwrap();
+            std::thread::scope(|scope| {
+                for t in 0..4 {
+                    let db = &db;
+                    scope.spawn(move || {
+                        let collection = if t % 2 == 0 { "a" } else { "b" };
+                        for i in 0..10 {
+                            let data = TestData {
+                                uuid: format!("{}-{}", t, i),
+                            };
+                            db.insert_data(collection, data).unwrap();
+                        }
+                    });
+                }
+            });
+            assert_eq!(db.count("a").unwrap(), 20);
+            assert_eq!(db.count("b").unwrap(), 20);
+        }
     }
 
     #[test]
-    fn test_query_data() {
+    fn test_dirty() {
         #[derive(Debug, Serialize, Deserialize, Clone)]
         struct TestData {
             uuid: String,
@@ -282,106 +3383,390 @@ mod test {
                 self.uuid.clone()
             }
         }
-        let (mut db, _db_dir) = setup();
-        db.create_collection("test").unwrap();
-        let data_1 = TestData {
-            uuid: "test".to_string(),
-            name: "test".to_string(),
+        let data = |uuid: &str, name: &str| TestData {
+            uuid: uuid.to_string(),
+            name: name.to_string(),
         };
-        db.insert_data("test", data_1.clone()).unwrap();
-        let data_2 = TestData {
-            uuid: "test2".to_string(),
-            name: "test2".to_string(),
-        };
-        db.insert_data("test", data_2.clone()).unwrap();
-        let r: TestData = db.query_data("test", "test").unwrap();
-        assert_eq!(r.uuid, data_1.uuid);
-        assert_eq!(r.name, data_1.name);
+        let pair = |collection: &str, uuid: &str| (collection.to_string(), uuid.to_string());
+        let (mut db, _db_dir) = setup();
+        db.create_collection("users").unwrap();
+        db.create_collection("posts").unwrap();
+        assert!(db.dirty().is_empty());
+        db.insert_data("users", data("a", "Ann")).unwrap();
+        db.insert_data("users", data("b", "Bob")).unwrap();
+        db.insert_data("posts", data("p", "Post")).unwrap();
+        db.clear_dirty();
+        assert!(db.dirty().is_empty());
+        db.update_data("users", data("a", "Anna")).unwrap();
+        db.delete_data::<TestData>("posts", "p").unwrap();
+        // failed mutations are not tracked
+        assert!(db.update_data("users", data("z", "Zoe")).is_err());
+        db.upsert_many("posts", vec![data("q", "Other")]).unwrap();
+        let _: Vec<TestData> = db.list_data("users").unwrap();
+        assert_eq!(
+            db.dirty(),
+            vec![pair("posts", "p"), pair("posts", "q"), pair("users", "a")]
+        );
     }
 
     #[test]
-    fn test_delete_data() {
+    fn test_id_key() {
         #[derive(Debug, Serialize, Deserialize, Clone)]
         struct TestData {
-            uuid: String,
-            name: String,
+            email: String,
         }
         impl Data for TestData {
             fn uuid(&self) -> String {
-                self.uuid.clone()
+                self.email.clone()
             }
         }
-        let (mut db, _db_dir) = setup();
-        db.create_collection("test").unwrap();
-        let data_1 = TestData {
-            uuid: "test".to_string(),
-            name: "test".to_string(),
-        };
-        db.insert_data("test", data_1.clone()).unwrap();
-        let data_2 = TestData {
-            uuid: "test2".to_string(),
-            name: "test2".to_string(),
+        let (db, db_dir) = setup();
+        db.create_collection("users").unwrap();
+        db.configure_collection(
+            "users",
+            CollectionConfig {
+                id_key: Some("email".to_string()),
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        let data = TestData {
+            email: "ann@example.com".to_string(),
         };
-        db.insert_data("test", data_2.clone()).unwrap();
-        db.delete_data::<TestData>("test", "test").unwrap();
-        let r: Vec<TestData> = db.read_collection("test").unwrap();
-        assert_eq!(r.len(), 1);
-        assert_eq!(r[0].uuid, data_2.uuid);
+        db.insert_data("users", data).unwrap();
+        // the id is not duplicated under the default key
+        let raw = fs::read_to_string(db_dir.path().join("users.json")).unwrap();
+        assert_eq!(raw, r#"[{"email":"ann@example.com"}]"#);
+        assert_eq!(db.ids("users").unwrap(), vec!["ann@example.com"]);
+        assert!(db.contains("users", "ann@example.com").unwrap());
+        assert!(!db.contains("users", "bob@example.com").unwrap());
+        // records written without the rust type are found by the same key
+        fs::write(
+            db_dir.path().join("users.json"),
+            r#"[{"email":"bob@example.com","uuid":"other"}]"#,
+        )
+        .unwrap();
+        assert_eq!(db.ids("users").unwrap(), vec!["bob@example.com"]);
     }
 
     #[test]
-    fn test_update_data() {
+    fn test_prune_empty_collections() {
         #[derive(Debug, Serialize, Deserialize, Clone)]
         struct TestData {
             uuid: String,
-            name: String,
         }
         impl Data for TestData {
             fn uuid(&self) -> String {
                 self.uuid.clone()
             }
         }
-        let (mut db, _db_dir) = setup();
-        db.create_collection("test").unwrap();
-        let data_1 = TestData {
-            uuid: "test".to_string(),
-            name: "test".to_string(),
-        };
-        db.insert_data("test", data_1.clone()).unwrap();
-        let data_2 = TestData {
-            uuid: "test".to_string(),
-            name: "test2".to_string(),
-        };
-        db.update_data("test", data_2.clone()).unwrap();
-        let r: Vec<TestData> = db.read_collection("test").unwrap();
-        assert_eq!(r.len(), 1);
-        assert_eq!(r[0].uuid, data_2.uuid);
-        assert_eq!(r[0].name, data_2.name);
+        let (db, db_dir) = setup();
+        for collection in ["empty", "full", "kept", "other"] {
+            db.create_collection(collection).unwrap();
+        }
+        db.insert_data(
+            "full",
+            TestData {
+                uuid: "a".to_string(),
+            },
+        )
+        .unwrap();
+        db.configure_collection(
+            "kept",
+            CollectionConfig {
+                persistent: true,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        fs::write(db_dir.path().join("broken.json"), "[").unwrap();
+        db.create_collection("broken_meta").unwrap();
+        fs::write(db_dir.path().join("broken_meta.meta"), "{").unwrap();
+        assert_eq!(
+            db.prune_empty_collections().unwrap(),
+            vec!["empty", "other"]
+        );
+        let mut collections = db.list_collections().unwrap();
+        collections.sort();
+        assert_eq!(collections, vec!["broken", "broken_meta", "full", "kept"]);
+        assert!(db.prune_empty_collections().unwrap().is_empty());
     }
 
     #[test]
-    fn test_list_collections(){
-        let (db, _db_dir) = setup();
-        db.create_collection("test").unwrap();
-        db.create_collection("test2").unwrap();
-        let r: Vec<String> = db.list_collections().unwrap();
-        assert_eq!(r.len(), 2);
+    fn test_bytes() {
+        let (db, db_dir) = setup();
+        let payload: Vec<u8> = (0..=255).collect();
+        assert!(db.list_byte_ids("blobs").unwrap().is_empty());
+        db.insert_bytes("blobs", "b", &payload).unwrap();
+        db.insert_bytes("blobs", "a", &[0xff, 0x00, 0xfe]).unwrap();
+        db.insert_bytes("blobs", "empty", &[]).unwrap();
+        assert!(db.insert_bytes("blobs", "a", &[1]).is_err());
+        assert!(db.insert_bytes("blobs", "../escape", &[1]).is_err());
+        assert_eq!(db.get_bytes("blobs", "b").unwrap(), payload);
+        assert_eq!(db.get_bytes("blobs", "a").unwrap(), vec![0xff, 0x00, 0xfe]);
+        assert!(db.get_bytes("blobs", "empty").unwrap().is_empty());
+        assert!(db.get_bytes("blobs", "missing").is_err());
+        assert_eq!(db.list_byte_ids("blobs").unwrap(), vec!["a", "b", "empty"]);
+        // payloads are stored as is, one file per record
+        assert_eq!(
+            fs::read(db_dir.path().join("blobs.bytes").join("b")).unwrap(),
+            payload
+        );
+        assert!(db.list_collections().unwrap().is_empty());
     }
 
     #[test]
-    fn test_delete_collection(){
+    fn test_scope() {
         let (db, _db_dir) = setup();
+        assert!(db.count("missing").unwrap_err().context().is_none());
+        let e = {
+            let _scope = db.scope("import users");
+            let e = db.count("missing").unwrap_err();
+            assert_eq!(e.context(), Some("import users"));
+            let _inner = db.scope("read file");
+            db.count("missing").unwrap_err()
+        };
+        assert_eq!(e.context(), Some("import users: read file"));
+        assert_eq!(
+            e.to_string(),
+            format!("DBError: import users: read file: {}", e.0)
+        );
+        assert!(db.count("missing").unwrap_err().context().is_none());
+        // scopes dropped out of order only remove themselves
+        let outer = db.scope("outer");
+        let inner = db.scope("inner");
+        drop(outer);
+        assert_eq!(db.count("missing").unwrap_err().context(), Some("inner"));
+        let _a = db.scope("a");
+        let _b = db.scope("b");
+        drop(inner);
+        assert_eq!(db.count("missing").unwrap_err().context(), Some("a: b"));
+    }
+
+    /// Reader generating a collection of `records` records without ever holding it in memory
+    struct GeneratedReader {
+        next: usize,
+        records: usize,
+        pending: Vec<u8>,
+        largest_read: Arc<AtomicUsize>,
+    }
+
+    impl Read for GeneratedReader {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            self.largest_read.fetch_max(buf.len(), Ordering::SeqCst);
+            if self.pending.is_empty() && self.next <= self.records {
+                self.pending = match self.next {
+                    0 => b"[".to_vec(),
+                    i if i == self.records => b"]".to_vec(),
+                    i => format!(
+                        r#"{}{{"uuid":"{}","n":{}}}"#,
+                        if i > 1 { "," } else { "" },
+                        i,
+                        i
+                    )
+                    .into_bytes(),
+                };
+                self.next += 1;
+            }
+            let n = buf.len().min(self.pending.len());
+            buf[..n].copy_from_slice(&self.pending[..n]);
+            self.pending.drain(..n);
+            Ok(n)
+        }
+    }
+
+    #[test]
+    fn test_aggregate() {
+        #[derive(Debug, Serialize, Deserialize, Clone)]
+        struct TestData {
+            uuid: String,
+            n: u64,
+        }
+        impl Data for TestData {
+            fn uuid(&self) -> String {
+                self.uuid.clone()
+            }
+        }
+        // records 1 to 99_999, several megabytes of json
+        let records = 100_000;
+        let largest_read = Arc::new(AtomicUsize::new(0));
+        let read = largest_read.clone();
+        let open: OpenHook = Box::new(move |path| {
+            let reader: Box<dyn Read + Send> = Box::new(GeneratedReader {
+                next: 0,
+                records,
+                pending: Vec::new(),
+                largest_read: read.clone(),
+            });
+            path.ends_with("big.json").then_some(reader)
+        });
+        let mut db = Database::new().with_file_system(TestFileSystem {
+            open: Some(open),
+            ..Default::default()
+        });
+        let db_dir = tempdir().unwrap();
+        db.connect(db_dir.path().to_path_buf()).unwrap();
+        // the file on disk is empty, its records are served by the open hook
+        fs::write(db_dir.path().join("big.json"), "").unwrap();
+        let (sum, max) = db
+            .aggregate("big", (0, 0), |(sum, max), r: &TestData| {
+                (sum + r.n, max.max(r.n))
+            })
+            .unwrap();
+        assert_eq!(sum, (1..records as u64).sum::<u64>());
+        assert_eq!(max, records as u64 - 1);
+        // the collection is only ever read through a small buffer
+        assert!(largest_read.load(Ordering::SeqCst) <= 8 * 1024);
+        assert!(db.aggregate("missing", 0, |n, _: &TestData| n + 1).is_err());
+    }
+
+    #[test]
+    fn test_migrate_dry_run() {
+        let (mut db, db_dir) = setup();
         db.create_collection("test").unwrap();
-        db.delete_collection("test").unwrap();
-        assert!(!db.path.join("test.json").exists());
+        db.write_collection(
+            "test",
+            vec![
+                serde_json::json!({"uuid": "a", "age": "1"}),
+                serde_json::json!({"uuid": "b", "age": "old"}),
+                serde_json::json!({"uuid": "c", "age": "3", "alias": "a"}),
+            ],
+        )
+        .unwrap();
+        db.register_migration("test", |mut v: Value| {
+            let age: u64 = v["age"]
+                .as_str()
+                .and_then(|a| a.parse().ok())
+                .ok_or(DBError::new("Age is not a number"))?;
+            v["age"] = Value::from(age);
+            Ok(v)
+        });
+        let before = fs::read_to_string(db_dir.path().join("test.json")).unwrap();
+        let report = db.migrate_dry_run("test").unwrap();
+        assert!(!report.is_ok());
+        assert!(!report.has_warnings());
+        assert_eq!((report.from, report.to), (0, 1));
+        assert_eq!(report.converted, 2);
+        assert_eq!(
+            report.failures,
+            vec![(1, "Age is not a number".to_string())]
+        );
+        // nothing was written
+        let after = fs::read_to_string(db_dir.path().join("test.json")).unwrap();
+        assert_eq!(before, after);
+        assert_eq!(db.read_meta("test").unwrap().version, 0);
+        // a step producing duplicate ids is reported too
+        db.register_migration("test", |mut v: Value| {
+            if let Some(alias) = v.get("alias").cloned() {
+                v["uuid"] = alias;
+            }
+            Ok(v)
+        });
+        let report = db.migrate_dry_run("test").unwrap();
+        assert_eq!(report.to, 2);
+        assert_eq!(report.converted, 0);
+        assert_eq!(
+            report.failures,
+            vec![
+                (0, "Duplicate id a".to_string()),
+                (1, "Age is not a number".to_string()),
+                (2, "Duplicate id a".to_string()),
+            ]
+        );
+        // and rejected by migrate
+        db.create_collection("dup").unwrap();
+        db.write_collection(
+            "dup",
+            vec![
+                serde_json::json!({"uuid": "a"}),
+                serde_json::json!({"uuid": "b"}),
+            ],
+        )
+        .unwrap();
+        db.register_migration("dup", |mut v: Value| {
+            v["uuid"] = Value::from("x");
+            Ok(v)
+        });
+        assert!(!db.migrate_dry_run("dup").unwrap().is_ok());
+        let r = db.migrate("dup").unwrap();
+        assert_eq!(r.error, Some("Duplicate id x".to_string()));
+        assert_eq!(r.to, 0);
+        assert_eq!(db.ids("dup").unwrap(), vec!["a", "b"]);
+        // records without stored ids are not warned about
+        db.create_collection("derived").unwrap();
+        db.write_collection("derived", vec![serde_json::json!({"name": "a"})])
+            .unwrap();
+        db.register_migration("derived", Ok);
+        let report = db.migrate_dry_run("derived").unwrap();
+        assert!(report.is_ok());
+        assert!(!report.has_warnings());
+        assert_eq!(report.converted, 1);
+        assert!(db.migrate("derived").unwrap().error.is_none());
     }
 
     #[test]
-    fn test_rename_collection(){
+    fn test_transaction_flush_on_drop() {
+        use crate::transaction::FlushOnDrop;
+        #[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+        struct TestData {
+            uuid: String,
+        }
+        impl Data for TestData {
+            fn uuid(&self) -> String {
+                self.uuid.clone()
+            }
+        }
+        let data = |uuid: &str| TestData {
+            uuid: uuid.to_string(),
+        };
         let (db, _db_dir) = setup();
         db.create_collection("test").unwrap();
-        db.rename_collection("test", "test2").unwrap();
-        assert!(!db.path.join("test.json").exists());
-        assert!(db.path.join("test2.json").exists());
+        assert!(db.transaction::<TestData>("missing").is_err());
+        // changes are only written on commit
+        let mut t = db.transaction("test").unwrap();
+        t.insert(data("a")).unwrap();
+        t.insert(data("b")).unwrap();
+        assert!(t.insert(data("a")).is_err());
+        assert_eq!(t.list().len(), 2);
+        assert_eq!(db.count("test").unwrap(), 0);
+        t.commit().unwrap();
+        assert_eq!(db.ids("test").unwrap(), vec!["a", "b"]);
+        // dropping discards by default
+        {
+            let mut t = db.transaction::<TestData>("test").unwrap();
+            t.delete("a").unwrap();
+        }
+        assert_eq!(db.count("test").unwrap(), 2);
+        {
+            let mut t = db
+                .transaction::<TestData>("test")
+                .unwrap()
+                .on_drop(FlushOnDrop::Commit);
+            t.delete("a").unwrap();
+        }
+        assert_eq!(db.ids("test").unwrap(), vec!["b"]);
+        let dropped = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            let mut t = db
+                .transaction::<TestData>("test")
+                .unwrap()
+                .on_drop(FlushOnDrop::Panic);
+            t.insert(data("c")).unwrap();
+        }));
+        assert!(dropped.is_err());
+        assert_eq!(db.ids("test").unwrap(), vec!["b"]);
+        // finished transactions never trigger the policy
+        let mut t = db
+            .transaction::<TestData>("test")
+            .unwrap()
+            .on_drop(FlushOnDrop::Panic);
+        t.insert(data("c")).unwrap();
+        t.rollback();
+        assert_eq!(db.ids("test").unwrap(), vec!["b"]);
+        // writes made after the transaction started are not overwritten
+        let mut t = db.transaction::<TestData>("test").unwrap();
+        db.insert_data("test", data("outside")).unwrap();
+        t.insert(data("inside")).unwrap();
+        assert!(t.commit().is_err());
+        assert_eq!(db.ids("test").unwrap(), vec!["b", "outside"]);
     }
 }
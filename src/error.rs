@@ -1,16 +1,75 @@
 use std::{
+    borrow::Cow,
+    cell::{Cell, RefCell},
     error::Error,
     fmt::{Display, Formatter, Result as FmtResult},
+    marker::PhantomData,
 };
 
-/// Error type for the DB
+thread_local! {
+    /// Ids and contexts of the [ErrorScope]s alive on this thread, outermost first
+    static SCOPES: RefCell<Vec<(u64, String)>> = const { RefCell::new(Vec::new()) };
+    /// Id of the next [ErrorScope] created on this thread
+    static NEXT_SCOPE: Cell<u64> = const { Cell::new(0) };
+}
+
+/// Error type for the DB, its message is either borrowed or built at runtime
 #[derive(Debug)]
-pub struct DBError<'a>(pub &'a str);
+pub struct DBError<'a>(pub Cow<'a, str>, Option<String>);
+
+impl<'a> DBError<'a> {
+    /// Creates an error from a `&str` or a `String`, tagged with the context of the
+    /// [ErrorScope]s alive on this thread
+    pub fn new(message: impl Into<Cow<'a, str>>) -> DBError<'a> {
+        let context = SCOPES.with(|s| {
+            let scopes = s.borrow();
+            (!scopes.is_empty()).then(|| {
+                let contexts: Vec<&str> = scopes.iter().map(|(_, c)| c.as_str()).collect();
+                contexts.join(": ")
+            })
+        });
+        DBError(message.into(), context)
+    }
+
+    /// Returns the context the error was created in, nested scopes are joined by `: `
+    pub fn context(&self) -> Option<&str> {
+        self.1.as_deref()
+    }
+}
 
 impl<'a> Display for DBError<'a> {
     fn fmt(&self, f: &mut Formatter) -> FmtResult {
-        write!(f, "DBError: {}", self.0)
+        match &self.1 {
+            Some(context) => write!(f, "DBError: {}: {}", context, self.0),
+            None => write!(f, "DBError: {}", self.0),
+        }
     }
 }
 
 impl<'a> Error for DBError<'a> {}
+
+/// Tags every [DBError] created on this thread with a context until dropped, see
+/// [crate::Database::scope]
+pub struct ErrorScope {
+    id: u64,
+    // scopes are thread local, so the guard must be dropped on the thread that created it
+    _not_send: PhantomData<*const ()>,
+}
+
+impl ErrorScope {
+    pub(crate) fn new(context: &str) -> ErrorScope {
+        let id = NEXT_SCOPE.with(|n| n.replace(n.get() + 1));
+        SCOPES.with(|s| s.borrow_mut().push((id, context.to_string())));
+        ErrorScope {
+            id,
+            _not_send: PhantomData,
+        }
+    }
+}
+
+impl Drop for ErrorScope {
+    // only removes this scope, guards may be dropped in any order
+    fn drop(&mut self) {
+        SCOPES.with(|s| s.borrow_mut().retain(|(id, _)| *id != self.id));
+    }
+}
@@ -0,0 +1,112 @@
+use std::fs;
+use std::io::{self, ErrorKind, Read};
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::Duration;
+
+/// Trait for the file system the database is stored on, [StdFileSystem] is used by default
+pub trait FileSystem: Send + Sync {
+    fn read_to_string(&self, path: &Path) -> io::Result<String>;
+    /// Opens a file for streaming reads
+    fn open(&self, path: &Path) -> io::Result<Box<dyn Read + Send>>;
+    fn write(&self, path: &Path, contents: &[u8]) -> io::Result<()>;
+    fn exists(&self, path: &Path) -> bool;
+    fn is_dir(&self, path: &Path) -> bool;
+    fn create_dir_all(&self, path: &Path) -> io::Result<()>;
+    fn remove_file(&self, path: &Path) -> io::Result<()>;
+    fn rename(&self, from: &Path, to: &Path) -> io::Result<()>;
+    /// Lists the files, not the subdirectories, of a directory
+    fn read_dir(&self, path: &Path) -> io::Result<Vec<PathBuf>>;
+    /// Lists the subdirectories of a directory
+    fn read_subdirs(&self, path: &Path) -> io::Result<Vec<PathBuf>>;
+}
+
+/// File system backed by [std::fs]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct StdFileSystem;
+
+impl FileSystem for StdFileSystem {
+    fn read_to_string(&self, path: &Path) -> io::Result<String> {
+        fs::read_to_string(path)
+    }
+
+    fn open(&self, path: &Path) -> io::Result<Box<dyn Read + Send>> {
+        Ok(Box::new(fs::File::open(path)?))
+    }
+
+    fn write(&self, path: &Path, contents: &[u8]) -> io::Result<()> {
+        fs::write(path, contents)
+    }
+
+    fn exists(&self, path: &Path) -> bool {
+        path.exists()
+    }
+
+    fn is_dir(&self, path: &Path) -> bool {
+        path.is_dir()
+    }
+
+    fn create_dir_all(&self, path: &Path) -> io::Result<()> {
+        fs::create_dir_all(path)
+    }
+
+    fn remove_file(&self, path: &Path) -> io::Result<()> {
+        fs::remove_file(path)
+    }
+
+    fn rename(&self, from: &Path, to: &Path) -> io::Result<()> {
+        fs::rename(from, to)
+    }
+
+    fn read_dir(&self, path: &Path) -> io::Result<Vec<PathBuf>> {
+        Ok(fs::read_dir(path)?
+            .filter_map(|r| r.ok())
+            .map(|r| r.path())
+            .filter(|r| r.is_file())
+            .collect())
+    }
+
+    fn read_subdirs(&self, path: &Path) -> io::Result<Vec<PathBuf>> {
+        Ok(fs::read_dir(path)?
+            .filter_map(|r| r.ok())
+            .map(|r| r.path())
+            .filter(|r| r.is_dir())
+            .collect())
+    }
+}
+
+/// Retries reads and writes failing with a transient io error, waiting `backoff` before the
+/// first retry and doubling the wait before every following one
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct RetryPolicy {
+    /// Number of retries after the first attempt, 0 disables retrying
+    pub retries: u32,
+    /// Wait before the first retry
+    pub backoff: Duration,
+}
+
+impl RetryPolicy {
+    /// Runs an io operation, retrying it as long as it fails with a transient error
+    pub fn run<R>(&self, mut op: impl FnMut() -> io::Result<R>) -> io::Result<R> {
+        let mut backoff = self.backoff;
+        let mut attempt = 0;
+        loop {
+            match op() {
+                Err(e) if attempt < self.retries && is_transient(&e) => {
+                    thread::sleep(backoff);
+                    backoff *= 2;
+                    attempt += 1;
+                }
+                r => return r,
+            }
+        }
+    }
+}
+
+/// Returns true for io errors that may succeed when retried (`EINTR`, `EAGAIN`, `ETXTBSY`)
+fn is_transient(e: &io::Error) -> bool {
+    matches!(
+        e.kind(),
+        ErrorKind::Interrupted | ErrorKind::WouldBlock | ErrorKind::ExecutableFileBusy
+    )
+}
@@ -0,0 +1,111 @@
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::cmp::Ordering;
+
+/// Secondary index over a top level field, stored in the collection metadata sidecar
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub(crate) struct Index {
+    /// Indexed field
+    pub(crate) field: String,
+    /// Field values with the id of their record, sorted by value then id
+    pub(crate) entries: Vec<(Value, String)>,
+}
+
+impl Index {
+    /// Builds an index over `field` from the records of a collection, records without the field
+    /// or without an id are left out
+    pub(crate) fn build(
+        field: &str,
+        records: &[Value],
+        id: impl Fn(&Value) -> Option<String>,
+    ) -> Index {
+        let mut entries: Vec<(Value, String)> = records
+            .iter()
+            .filter_map(|r| Some((r.get(field)?.clone(), id(r)?)))
+            .collect();
+        entries.sort_by(|a, b| cmp_values(&a.0, &b.0).then_with(|| a.1.cmp(&b.1)));
+        Index {
+            field: field.to_string(),
+            entries,
+        }
+    }
+}
+
+/// Total order over json values, values of different types are ordered
+/// null < bool < number < string < array < object
+pub(crate) fn cmp_values(a: &Value, b: &Value) -> Ordering {
+    fn rank(v: &Value) -> u8 {
+        match v {
+            Value::Null => 0,
+            Value::Bool(_) => 1,
+            Value::Number(_) => 2,
+            Value::String(_) => 3,
+            Value::Array(_) => 4,
+            Value::Object(_) => 5,
+        }
+    }
+    match (a, b) {
+        (Value::Bool(a), Value::Bool(b)) => a.cmp(b),
+        (Value::Number(a), Value::Number(b)) => match (a.as_i64(), b.as_i64()) {
+            (Some(a), Some(b)) => a.cmp(&b),
+            _ => a
+                .as_f64()
+                .unwrap_or_default()
+                .total_cmp(&b.as_f64().unwrap_or_default()),
+        },
+        (Value::String(a), Value::String(b)) => a.cmp(b),
+        (Value::Array(_), Value::Array(_)) | (Value::Object(_), Value::Object(_)) => {
+            a.to_string().cmp(&b.to_string())
+        }
+        _ => rank(a).cmp(&rank(b)),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_cmp_values() {
+        let mut values = vec![
+            json!("b"),
+            json!(10),
+            json!(null),
+            json!(2.5),
+            json!("a"),
+            json!(true),
+        ];
+        values.sort_by(cmp_values);
+        assert_eq!(
+            values,
+            vec![
+                json!(null),
+                json!(true),
+                json!(2.5),
+                json!(10),
+                json!("a"),
+                json!("b")
+            ]
+        );
+    }
+
+    #[test]
+    fn test_build() {
+        let records = vec![
+            json!({"uuid": "1", "age": 30}),
+            json!({"uuid": "2", "age": 20}),
+            json!({"uuid": "3"}),
+            json!({"uuid": "4", "age": 20}),
+        ];
+        let index = Index::build("age", &records, |r| r["uuid"].as_str().map(String::from));
+        assert_eq!(
+            index.entries,
+            vec![
+                (json!(20), "2".to_string()),
+                (json!(20), "4".to_string()),
+                (json!(30), "1".to_string()),
+            ]
+        );
+    }
+}
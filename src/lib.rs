@@ -1,33 +1,60 @@
 //! # Amandine
 //! Amandine is a tiny json database for rust. It is designed to be used in small, embedded, or
 //! client-side projects.
+//! ## Features
+//! * `pretty` (default) - pretty printed collection files, see [config::CollectionConfig]
+//! * `indexes` (default) - ordered secondary indexes, see [db::TDatabase::create_index]
+//!
+//! Disabling the default features leaves the core operations on compact json. The settings and
+//! indexes of the disabled features are still kept in the collection metadata, so builds with
+//! different features can share a database. Indexes are only updated by builds with the
+//! `indexes` feature.
 //! ## Usage
 //! ```rust
 //! use amandine::{Data, Database};
+//! use amandine::db::TDatabase;
 //! use serde::{Serialize, Deserialize};
-//! use std::fs;
-//! use std::path::Path;
 //!
-//! #[derive(Serialize, Deserialize, Debug, Clone, Data)]
+//! #[derive(Serialize, Deserialize, Debug, Clone)]
 //! struct User {
 //!     name: String,
 //!     age: u8,
 //! }
 //!
+//! impl Data for User {
+//!     fn uuid(&self) -> String {
+//!         self.name.clone()
+//!     }
+//! }
+//!
 //! fn main() {
-//!     let db = Database::new();
-//!     let dbPath = Path::new("./db"); // should be folder/dir path
-//!     db.connect(dbPath.to_path_buf()).unwrap();
+//!     let mut db = Database::new();
+//!     let db_dir = tempfile::tempdir().unwrap();
+//!     db.connect(db_dir.path().to_path_buf()).unwrap(); // should be folder/dir path
 //!     db.create_collection("users").unwrap(); // create a collection to store data
 //!     let user = User {
 //!         name: "John".to_string(),
 //!         age: 20,
-//!     }
-//!     db.insert("users", user).unwrap(); // insert data into collection
+//!     };
+//!     db.insert_data("users", user).unwrap(); // insert data into collection
 //! }
+//! ```
 
+pub mod backend;
+mod cache;
+pub mod collection;
+pub mod config;
 pub mod db;
 pub mod error;
+pub mod filesystem;
+#[cfg(feature = "indexes")]
+mod index;
+mod lock;
+pub mod migration;
+mod stream;
+pub mod transaction;
 
+pub use collection::{Collection, TypedCollection};
 pub use db::Data;
 pub use db::Database;
+pub use transaction::Transaction;
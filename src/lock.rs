@@ -0,0 +1,70 @@
+use std::collections::BTreeSet;
+use std::sync::{Condvar, Mutex};
+
+use crate::config::LockGranularity;
+
+/// Set of locked collections, writers wait until none of the collections they use is locked
+pub(crate) struct LockSet {
+    granularity: LockGranularity,
+    locked: Mutex<BTreeSet<String>>,
+    released: Condvar,
+}
+
+/// Holds the locks of a set of collections until dropped
+pub(crate) struct LockGuard<'a> {
+    set: &'a LockSet,
+    keys: BTreeSet<String>,
+}
+
+impl LockSet {
+    pub(crate) fn new(granularity: LockGranularity) -> LockSet {
+        LockSet {
+            granularity,
+            locked: Mutex::new(BTreeSet::new()),
+            released: Condvar::new(),
+        }
+    }
+
+    /// Locks all the given collections at once, blocking while any of them is locked
+    pub(crate) fn lock<'n>(&self, collections: impl IntoIterator<Item = &'n str>) -> LockGuard<'_> {
+        let keys: BTreeSet<String> = match self.granularity {
+            LockGranularity::PerCollection => {
+                collections.into_iter().map(|c| c.to_lowercase()).collect()
+            }
+            LockGranularity::Global => BTreeSet::from([String::new()]),
+        };
+        let mut locked = self.locked.lock().unwrap();
+        while keys.iter().any(|k| locked.contains(k)) {
+            locked = self.released.wait(locked).unwrap();
+        }
+        locked.extend(keys.iter().cloned());
+        LockGuard { set: self, keys }
+    }
+}
+
+impl Drop for LockGuard<'_> {
+    fn drop(&mut self) {
+        let mut locked = self.set.locked.lock().unwrap();
+        for key in &self.keys {
+            locked.remove(key);
+        }
+        self.set.released.notify_all();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_lock_keys() {
+        let set = LockSet::new(LockGranularity::PerCollection);
+        let guard = set.lock(["a", "B", "b"]);
+        assert_eq!(guard.keys.len(), 2);
+        assert!(set.locked.lock().unwrap().contains("b"));
+        drop(guard);
+        assert!(set.locked.lock().unwrap().is_empty());
+        let set = LockSet::new(LockGranularity::Global);
+        assert_eq!(set.lock(["a", "b"]).keys.len(), 1);
+    }
+}
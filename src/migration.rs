@@ -0,0 +1,64 @@
+use serde_json::Value;
+
+use crate::error::DBError;
+
+/// A single migration step, transforms one record from version `n` to version `n + 1`
+pub type Migration = Box<dyn Fn(Value) -> Result<Value, DBError<'static>> + Send + Sync>;
+
+/// Outcome of migrating a single collection
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CollectionMigration {
+    /// Name of the migrated collection
+    pub collection: String,
+    /// Version the collection was at before migrating
+    pub from: u32,
+    /// Version the collection is at after migrating
+    pub to: u32,
+    /// Error of the step that failed, the collection is left at `to`
+    pub error: Option<String>,
+}
+
+/// Report returned by [crate::db::TDatabase::migrate_all]
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MigrationReport {
+    /// One entry per registered collection
+    pub collections: Vec<CollectionMigration>,
+}
+
+impl MigrationReport {
+    /// Returns true if every collection was migrated to its latest version
+    pub fn is_ok(&self) -> bool {
+        self.collections.iter().all(|c| c.error.is_none())
+    }
+}
+
+/// Report returned by [crate::db::TDatabase::migrate_dry_run]
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MigrationDryRun {
+    /// Name of the checked collection
+    pub collection: String,
+    /// Version the collection is at
+    pub from: u32,
+    /// Version the collection would be at after migrating
+    pub to: u32,
+    /// Number of records that would migrate cleanly
+    pub converted: usize,
+    /// Position in the collection and error of every record that would fail to migrate, sorted
+    /// by position. Records sharing a stored id after a step fail too, the migration fails if
+    /// there is any
+    pub failures: Vec<(usize, String)>,
+    /// Warning, position of the records whose stored id would be removed by the migration
+    pub lost_ids: Vec<usize>,
+}
+
+impl MigrationDryRun {
+    /// Returns true if [crate::db::TDatabase::migrate] would succeed
+    pub fn is_ok(&self) -> bool {
+        self.failures.is_empty()
+    }
+
+    /// Returns true if the migrated records would lose ids
+    pub fn has_warnings(&self) -> bool {
+        !self.lost_ids.is_empty()
+    }
+}
@@ -0,0 +1,146 @@
+use std::io::{self, BufReader, Bytes, Read};
+
+/// Splits a json array read from `R` into the raw bytes of its elements, one element at a time,
+/// so only a single record is held in memory
+pub(crate) struct RecordStream<R: Read> {
+    bytes: Bytes<BufReader<R>>,
+    state: State,
+}
+
+#[derive(PartialEq, Eq)]
+enum State {
+    Start,
+    Elements,
+    Done,
+}
+
+impl<R: Read> RecordStream<R> {
+    pub(crate) fn new(reader: R) -> RecordStream<R> {
+        RecordStream {
+            bytes: BufReader::new(reader).bytes(),
+            state: State::Start,
+        }
+    }
+
+    /// Returns the next byte that is not json whitespace
+    fn next_significant(&mut self) -> io::Result<Option<u8>> {
+        for b in self.bytes.by_ref() {
+            let b = b?;
+            if !b.is_ascii_whitespace() {
+                return Ok(Some(b));
+            }
+        }
+        Ok(None)
+    }
+
+    /// Reads an element starting with `first` up to the `,` or `]` following it
+    fn read_element(&mut self, first: u8) -> io::Result<Vec<u8>> {
+        let mut element = vec![first];
+        let mut depth = 0usize;
+        let mut in_string = first == b'"';
+        let mut escaped = false;
+        if first == b'[' || first == b'{' {
+            depth += 1;
+        }
+        for b in self.bytes.by_ref() {
+            let b = b?;
+            if in_string {
+                if escaped {
+                    escaped = false;
+                } else if b == b'\\' {
+                    escaped = true;
+                } else if b == b'"' {
+                    in_string = false;
+                }
+            } else {
+                match b {
+                    b'"' => in_string = true,
+                    b'[' | b'{' => depth += 1,
+                    b']' | b'}' if depth > 0 => depth -= 1,
+                    b',' if depth == 0 => return Ok(element),
+                    b']' if depth == 0 => {
+                        self.state = State::Done;
+                        return Ok(element);
+                    }
+                    _ => {}
+                }
+            }
+            element.push(b);
+        }
+        Err(malformed())
+    }
+}
+
+impl<R: Read> Iterator for RecordStream<R> {
+    type Item = io::Result<Vec<u8>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let r = match self.state {
+            State::Done => return None,
+            State::Start => match self.next_significant() {
+                Ok(Some(b'[')) => match self.next_significant() {
+                    Ok(Some(b']')) => {
+                        self.state = State::Done;
+                        return None;
+                    }
+                    Ok(Some(b)) => {
+                        self.state = State::Elements;
+                        self.read_element(b)
+                    }
+                    Ok(None) => Err(malformed()),
+                    Err(e) => Err(e),
+                },
+                Ok(_) => Err(malformed()),
+                Err(e) => Err(e),
+            },
+            State::Elements => match self.next_significant() {
+                Ok(Some(b)) if b != b',' && b != b']' => self.read_element(b),
+                Ok(_) => Err(malformed()),
+                Err(e) => Err(e),
+            },
+        };
+        if r.is_err() {
+            self.state = State::Done;
+        }
+        Some(r)
+    }
+}
+
+fn malformed() -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, "Collection is not a json array")
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn split(s: &str) -> Vec<io::Result<Vec<u8>>> {
+        RecordStream::new(s.as_bytes()).collect()
+    }
+
+    #[test]
+    fn test_record_stream() {
+        let r: Vec<Vec<u8>> = split(r#" [ {"a": [1, "]"]}, "x\"," ,3 ] "#)
+            .into_iter()
+            .map(|r| r.unwrap())
+            .collect();
+        assert_eq!(
+            r,
+            vec![
+                br#"{"a": [1, "]"]}"#.to_vec(),
+                br#""x\"," "#.to_vec(),
+                b"3 ".to_vec()
+            ]
+        );
+        assert!(split("[]").is_empty());
+    }
+
+    #[test]
+    fn test_record_stream_malformed() {
+        let r = split(r#"[{"a": 1}, {"b": "#);
+        assert_eq!(r.len(), 2);
+        assert!(r[0].is_ok());
+        assert!(r[1].is_err());
+        assert!(split("{}")[0].is_err());
+    }
+}
@@ -0,0 +1,136 @@
+use std::collections::BTreeSet;
+use std::thread;
+
+use serde_json::Value;
+
+use crate::db::{Data, Database};
+use crate::error::DBError;
+
+/// What happens to the buffered changes of a handle dropped without committing
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum FlushOnDrop {
+    /// Writes the changes, errors and conflicting writes are ignored and the changes lost
+    Commit,
+    /// Throws the changes away
+    #[default]
+    Discard,
+    /// Panics if there are changes, unless the thread is already panicking
+    Panic,
+}
+
+/// Buffered changes to a collection, created by [Database::transaction]. The records are read
+/// once when the transaction starts and written in a single write by [Transaction::commit],
+/// which fails without writing if the collection was changed since the transaction started
+pub struct Transaction<'a, T: Data> {
+    db: &'a Database,
+    name: String,
+    /// Stored records the transaction started from
+    base: Vec<Value>,
+    records: Vec<T>,
+    changed: BTreeSet<String>,
+    on_drop: FlushOnDrop,
+    finished: bool,
+}
+
+impl<'a, T: Data> Transaction<'a, T> {
+    pub(crate) fn new(
+        db: &'a Database,
+        name: &str,
+        base: Vec<Value>,
+        records: Vec<T>,
+    ) -> Transaction<'a, T> {
+        Transaction {
+            db,
+            name: name.to_string(),
+            base,
+            records,
+            changed: BTreeSet::new(),
+            on_drop: FlushOnDrop::default(),
+            finished: false,
+        }
+    }
+
+    /// Sets what happens if the transaction is dropped without committing, see [FlushOnDrop]
+    pub fn on_drop(mut self, policy: FlushOnDrop) -> Transaction<'a, T> {
+        self.on_drop = policy;
+        self
+    }
+
+    /// Name of the collection
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Inserts data into the transaction
+    pub fn insert(&mut self, data: T) -> Result<(), DBError<'a>> {
+        let uuid = self.db.insert_record(&self.name, data, &mut self.records)?;
+        self.changed.insert(uuid);
+        Result::Ok(())
+    }
+
+    /// Queries data from the transaction
+    pub fn query(&self, uuid: &str) -> Result<T, DBError<'a>> {
+        self.records
+            .iter()
+            .find(|i| i.uuid() == uuid)
+            .cloned()
+            .ok_or(DBError::new("Data not found"))
+    }
+
+    /// Updates data in the transaction
+    pub fn update(&mut self, data: T) -> Result<(), DBError<'a>> {
+        let uuid = self.db.update_record(&self.name, data, &mut self.records)?;
+        self.changed.insert(uuid);
+        Result::Ok(())
+    }
+
+    /// Deletes data from the transaction
+    pub fn delete(&mut self, uuid: &str) -> Result<(), DBError<'a>> {
+        self.db.delete_record(uuid, &mut self.records)?;
+        self.changed.insert(uuid.to_string());
+        Result::Ok(())
+    }
+
+    /// Lists data from the transaction, including its uncommitted changes
+    pub fn list(&self) -> &[T] {
+        &self.records
+    }
+
+    /// Writes the changes of the transaction to the collection, fails with `Collection changed
+    /// since the transaction started` if another write happened in between
+    pub fn commit(mut self) -> Result<(), DBError<'a>> {
+        self.finished = true;
+        self.write()
+    }
+
+    /// Throws the changes of the transaction away
+    pub fn rollback(mut self) {
+        self.finished = true;
+    }
+
+    fn write(&mut self) -> Result<(), DBError<'a>> {
+        let records = std::mem::take(&mut self.records);
+        let changed = std::mem::take(&mut self.changed);
+        self.db
+            .commit_records(&self.name, &self.base, records, changed)
+    }
+}
+
+impl<T: Data> Drop for Transaction<'_, T> {
+    fn drop(&mut self) {
+        if self.finished || self.changed.is_empty() {
+            return;
+        }
+        match self.on_drop {
+            FlushOnDrop::Commit => {
+                let _ = self.write();
+            }
+            FlushOnDrop::Discard => {}
+            FlushOnDrop::Panic => {
+                if !thread::panicking() {
+                    panic!("Transaction on `{}` dropped without committing", self.name);
+                }
+            }
+        }
+    }
+}
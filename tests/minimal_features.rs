@@ -0,0 +1,107 @@
+//! Core operations, meant to also run without the optional features:
+//! `cargo test --no-default-features --test minimal_features`
+use amandine::{
+    self,
+    config::CollectionConfig,
+    db::{Data, Database, TDatabase},
+};
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+struct TestData {
+    uuid: String,
+    age: u8,
+}
+
+impl Data for TestData {
+    fn uuid(&self) -> String {
+        self.uuid.clone()
+    }
+}
+
+#[test]
+fn minimal_core_operations() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let mut db = Database::new();
+    db.connect(temp_dir.path().to_path_buf()).unwrap();
+    db.create_collection("test").unwrap();
+    db.configure_collection(
+        "test",
+        CollectionConfig {
+            sorted: true,
+            ..Default::default()
+        },
+    )
+    .unwrap();
+
+    let data = |uuid: &str, age: u8| TestData {
+        uuid: uuid.to_string(),
+        age,
+    };
+    db.insert_data("test", data("b", 20)).unwrap();
+    db.insert_data("test", data("a", 30)).unwrap();
+    assert_eq!(db.ids("test").unwrap(), vec!["a", "b"]);
+
+    db.update_data("test", data("b", 21)).unwrap();
+    assert_eq!(
+        db.query_data::<TestData>("test", "b").unwrap(),
+        data("b", 21)
+    );
+
+    db.delete_data::<TestData>("test", "a").unwrap();
+    assert_eq!(
+        db.list_data::<TestData>("test").unwrap(),
+        vec![data("b", 21)]
+    );
+
+    // collection files are compact json without the pretty feature
+    let content = std::fs::read_to_string(temp_dir.path().join("test.json")).unwrap();
+    assert!(!content.contains('\n'));
+
+    db.rename_collection("test", "test2").unwrap();
+    assert_eq!(db.list_collections().unwrap(), vec!["test2"]);
+    db.delete_collection("test2").unwrap();
+    assert!(db.list_collections().unwrap().is_empty());
+}
+
+#[test]
+fn metadata_of_disabled_features_is_kept() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let mut db = Database::new();
+    db.connect(temp_dir.path().to_path_buf()).unwrap();
+    db.create_collection("test").unwrap();
+    // written by a build with every feature
+    std::fs::write(
+        temp_dir.path().join("test.meta"),
+        r#"{"config":{"pretty":true},"indexes":{"by_age":{"field":"age","entries":[]}}}"#,
+    )
+    .unwrap();
+    db.configure_collection(
+        "test",
+        CollectionConfig {
+            sorted: true,
+            pretty: true,
+            ..Default::default()
+        },
+    )
+    .unwrap();
+    db.insert_data(
+        "test",
+        TestData {
+            uuid: "a".to_string(),
+            age: 30,
+        },
+    )
+    .unwrap();
+    let meta: serde_json::Value =
+        serde_json::from_str(&std::fs::read_to_string(temp_dir.path().join("test.meta")).unwrap())
+            .unwrap();
+    assert_eq!(meta["config"]["pretty"], true);
+    // without the indexes feature, stored indexes are kept as they are
+    let entries = if cfg!(feature = "indexes") {
+        serde_json::json!([[30, "a"]])
+    } else {
+        serde_json::json!([])
+    };
+    assert_eq!(meta["indexes"]["by_age"]["entries"], entries);
+}